@@ -1 +1,457 @@
+use crate::config::Config;
+use crate::layout::Rect;
+use log::{info, warn};
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    AtomEnum, ChangeGCAux, Char2b, ClientMessageEvent, ConfigureWindowAux, ConnectionExt,
+    CreateGCAux, CreateWindowAux, EventMask, Font, Gcontext, PropMode, Rectangle, Screen,
+    VisualClass, Visualtype, Window, WindowClass,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::COPY_FROM_PARENT;
 
+x11rb::atom_manager! {
+    // Atoms used by the bar, interned all at once when connecting.
+    pub BarAtoms: BarAtomsCookie {
+        UTF8_STRING,
+        _NET_WM_NAME,
+        _NET_WM_WINDOW_TYPE,
+        _NET_WM_WINDOW_TYPE_DOCK,
+        _NET_WM_STRUT,
+        _NET_WM_STRUT_PARTIAL,
+        _HIPPOWM_BAR_UPDATE,
+    }
+}
+
+// Space between the text of a workspace and its neighbours.
+const PADDING: i16 = 6;
+// Size of the square marking workspaces with clients.
+const OCCUPIED_SIZE: u16 = 4;
+
+// What the bar shows, the backends hand it over whenever it may have changed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Status {
+    // every workspace of the config, in the order of the config
+    pub workspaces: Vec<WorkspaceStatus>,
+    pub layout: String,
+    // title of the focused window, empty if no window has focus
+    pub title: String,
+    // the monitor the bar is shown on top of
+    pub area: Rect,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WorkspaceStatus {
+    pub name: String,
+    pub focused: bool,
+    // the workspace has clients
+    pub occupied: bool,
+    // one of its clients demands attention
+    pub urgent: bool,
+}
+
+// A bar along the top of the first monitor, drawn with core X fonts on a connection and
+// thread of its own. It is a dock window, so both backends leave it unmanaged and reserve
+// the space its strut asks for. Clones share the bar.
+#[derive(Clone)]
+pub struct Bar {
+    conn: Arc<RustConnection>,
+    window: Window,
+    atoms: BarAtoms,
+    status: Arc<Mutex<Status>>,
+}
+
+impl Bar {
+    // Creates the bar window and maps it once the first status arrives, which tells it the
+    // monitor to cover.
+    pub fn spawn(display: Option<&str>, config: &Config) -> Result<Bar, Box<dyn Error>> {
+        let height: u16 = config.bar_height.clamp(1, u16::MAX.into()) as u16;
+        let (conn, screen) = x11rb::connect(display)?;
+        let root: Window = conn.setup().roots[screen].root;
+        let atoms: BarAtoms = BarAtoms::new(&conn)?.reply()?;
+        let colors: Colors = Colors::new(&conn, &conn.setup().roots[screen], config)?;
+
+        let window: Window = conn.generate_id()?;
+        conn.create_window(
+            COPY_FROM_PARENT as u8,
+            window,
+            root,
+            0,
+            0,
+            1,
+            height,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            COPY_FROM_PARENT,
+            &CreateWindowAux::new()
+                .background_pixel(colors.background)
+                .event_mask(EventMask::EXPOSURE),
+        )?;
+        conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            atoms._NET_WM_WINDOW_TYPE,
+            AtomEnum::ATOM,
+            &[atoms._NET_WM_WINDOW_TYPE_DOCK],
+        )?;
+        conn.change_property8(
+            PropMode::REPLACE,
+            window,
+            AtomEnum::WM_CLASS,
+            AtomEnum::STRING,
+            b"hippowm-bar\0HippoWM\0",
+        )?;
+        conn.change_property8(
+            PropMode::REPLACE,
+            window,
+            atoms._NET_WM_NAME,
+            atoms.UTF8_STRING,
+            b"hippowm-bar",
+        )?;
+
+        let font: Font = conn.generate_id()?;
+        conn.open_font(font, config.bar_font.as_bytes())?.check()?;
+        let gc: Gcontext = conn.generate_id()?;
+        conn.create_gc(gc, window, &CreateGCAux::new().font(font))?;
+        let metrics = conn.query_font(font)?.reply()?;
+        conn.flush()?;
+
+        let bar: Bar = Bar {
+            conn: Arc::new(conn),
+            window,
+            atoms,
+            status: Arc::new(Mutex::new(Status::default())),
+        };
+        let painter: Painter = Painter {
+            bar: bar.clone(),
+            gc,
+            font,
+            ascent: metrics.font_ascent,
+            descent: metrics.font_descent,
+            height,
+            colors,
+            area: None,
+        };
+        thread::spawn(move || painter.run());
+        info!("Created bar window {}", window);
+        Ok(bar)
+    }
+
+    // Shows the given status, the bar is only drawn again if it changed.
+    pub fn update(&self, status: Status) {
+        match self.status.lock() {
+            Ok(mut current) if *current != status => *current = status,
+            Ok(_) => return,
+            Err(_) => {
+                warn!("The bar is not updated any more, it panicked while drawing");
+                return;
+            }
+        }
+        let event =
+            ClientMessageEvent::new(32, self.window, self.atoms._HIPPOWM_BAR_UPDATE, [0u32; 5]);
+        // without an event mask the event goes to the connection which created the window
+        let sent = self
+            .conn
+            .send_event(false, self.window, EventMask::NO_EVENT, event)
+            .and_then(|_| self.conn.flush());
+        if let Err(error) = sent {
+            warn!("Could not update the bar: {}", error);
+        }
+    }
+}
+
+// Pixel values of the colours of the config.
+struct Colors {
+    background: u32,
+    foreground: u32,
+    focused: u32,
+    urgent: u32,
+}
+
+impl Colors {
+    // The RGB of a colour is its pixel value on 24 bit TrueColor visuals, which nearly every
+    // server uses. Other visuals get the colours allocated in the default colormap.
+    fn new(
+        conn: &RustConnection,
+        screen: &Screen,
+        config: &Config,
+    ) -> Result<Colors, Box<dyn Error>> {
+        let true_color: bool = screen
+            .allowed_depths
+            .iter()
+            .flat_map(|depth| &depth.visuals)
+            .find(|visual| visual.visual_id == screen.root_visual)
+            .is_some_and(is_rgb24);
+        let pixel_of = |color: u32| -> Result<u32, Box<dyn Error>> {
+            if true_color {
+                return Ok(pixel(color));
+            }
+            let [red, green, blue, _] = color.to_be_bytes();
+            // X colours have 16 bit channels, 0xff becomes 0xffff
+            let reply = conn
+                .alloc_color(
+                    screen.default_colormap,
+                    u16::from(red) * 257,
+                    u16::from(green) * 257,
+                    u16::from(blue) * 257,
+                )?
+                .reply()?;
+            Ok(reply.pixel)
+        };
+        Ok(Colors {
+            background: pixel_of(config.bar_background)?,
+            foreground: pixel_of(config.bar_foreground)?,
+            focused: pixel_of(config.focused_border)?,
+            urgent: pixel_of(config.urgent_border)?,
+        })
+    }
+}
+
+// Draws the bar on its own thread, whenever it is exposed or updated.
+struct Painter {
+    bar: Bar,
+    gc: Gcontext,
+    font: Font,
+    ascent: i16,
+    descent: i16,
+    height: u16,
+    colors: Colors,
+    // the monitor the bar was last placed on
+    area: Option<Rect>,
+}
+
+impl Painter {
+    fn run(mut self) {
+        loop {
+            let event: Event = match self.bar.conn.wait_for_event() {
+                Ok(event) => event,
+                Err(error) => {
+                    warn!("Lost the connection of the bar: {}", error);
+                    return;
+                }
+            };
+            let status: Status = match self.bar.status.lock() {
+                Ok(status) => status.clone(),
+                Err(_) => {
+                    warn!("The bar stops drawing, a thread panicked while updating it");
+                    return;
+                }
+            };
+            let drawn = match event {
+                Event::Expose(e) if e.count == 0 => self.draw(&status),
+                Event::ClientMessage(e) if e.type_ == self.bar.atoms._HIPPOWM_BAR_UPDATE => {
+                    self.place(status.area).and_then(|_| self.draw(&status))
+                }
+                _ => continue,
+            };
+            if let Err(error) = drawn {
+                warn!("Could not draw the bar: {}", error);
+            }
+            if self.bar.conn.flush().is_err() {
+                return;
+            }
+        }
+    }
+
+    // Moves the bar to the top of the monitor and reserves the space it covers, the first
+    // time it is placed it is mapped as well.
+    fn place(&mut self, area: Rect) -> Result<(), Box<dyn Error>> {
+        if self.area == Some(area) {
+            return Ok(());
+        }
+        let conn = &self.bar.conn;
+        let window: Window = self.bar.window;
+        let strut: [u32; 12] = strut(area, self.height.into());
+        conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            self.bar.atoms._NET_WM_STRUT_PARTIAL,
+            AtomEnum::CARDINAL,
+            &strut,
+        )?;
+        conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            self.bar.atoms._NET_WM_STRUT,
+            AtomEnum::CARDINAL,
+            &strut[..4],
+        )?;
+        conn.configure_window(
+            window,
+            &ConfigureWindowAux::new()
+                .x(area.x)
+                .y(area.y)
+                .width(area.w.max(1))
+                .height(u32::from(self.height)),
+        )?;
+        if self.area.is_none() {
+            conn.map_window(window)?;
+        }
+        self.area = Some(area);
+        Ok(())
+    }
+
+    // Workspaces on the left, followed by the layout and the title of the focused window.
+    fn draw(&self, status: &Status) -> Result<(), Box<dyn Error>> {
+        let width: u16 = status.area.w.min(u16::MAX as u32) as u16;
+        self.fill(self.colors.background, 0, width)?;
+
+        let mut x: i16 = 0;
+        for ws in &status.workspaces {
+            let text: Vec<u8> = latin1(&ws.name);
+            let w: i16 = self.text_width(&text)? + 2 * PADDING;
+            let background: u32 = if ws.focused {
+                self.colors.focused
+            } else if ws.urgent {
+                self.colors.urgent
+            } else {
+                self.colors.background
+            };
+            self.fill(background, x, w as u16)?;
+            if ws.occupied {
+                self.bar.conn.change_gc(
+                    self.gc,
+                    &ChangeGCAux::new().foreground(self.colors.foreground),
+                )?;
+                self.bar.conn.poly_fill_rectangle(
+                    self.bar.window,
+                    self.gc,
+                    &[Rectangle {
+                        x: x + 1,
+                        y: 1,
+                        width: OCCUPIED_SIZE,
+                        height: OCCUPIED_SIZE.min(self.height),
+                    }],
+                )?;
+            }
+            self.text(background, x + PADDING, &text)?;
+            x += w;
+        }
+
+        let layout: Vec<u8> = latin1(&format!("[{}]", status.layout));
+        self.text(self.colors.background, x + PADDING, &layout)?;
+        x += self.text_width(&layout)? + 2 * PADDING;
+        self.text(self.colors.background, x, &latin1(&status.title))?;
+        Ok(())
+    }
+
+    fn fill(&self, color: u32, x: i16, width: u16) -> Result<(), Box<dyn Error>> {
+        let conn = &self.bar.conn;
+        conn.change_gc(self.gc, &ChangeGCAux::new().foreground(color))?;
+        conn.poly_fill_rectangle(
+            self.bar.window,
+            self.gc,
+            &[Rectangle {
+                x,
+                y: 0,
+                width,
+                height: self.height,
+            }],
+        )?;
+        Ok(())
+    }
+
+    // Draws the text vertically centered, on the given background.
+    fn text(&self, background: u32, x: i16, text: &[u8]) -> Result<(), Box<dyn Error>> {
+        let conn = &self.bar.conn;
+        conn.change_gc(
+            self.gc,
+            &ChangeGCAux::new()
+                .foreground(self.colors.foreground)
+                .background(background),
+        )?;
+        let baseline: i16 = (self.height as i16 - (self.ascent + self.descent)) / 2 + self.ascent;
+        conn.image_text8(self.bar.window, self.gc, x, baseline, text)?;
+        Ok(())
+    }
+
+    fn text_width(&self, text: &[u8]) -> Result<i16, Box<dyn Error>> {
+        let chars: Vec<Char2b> = text
+            .iter()
+            .map(|c| Char2b {
+                byte1: 0,
+                byte2: *c,
+            })
+            .collect();
+        let extents = self
+            .bar
+            .conn
+            .query_text_extents(self.font, &chars)?
+            .reply()?;
+        Ok(extents.overall_width as i16)
+    }
+}
+
+// Core fonts draw Latin-1, other characters are replaced. ImageText8 takes at most 255.
+fn latin1(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+        .take(255)
+        .collect()
+}
+
+// _NET_WM_STRUT_PARTIAL of a bar of the given height on top of the monitor area. Struts
+// are measured from the edge of the root window, the first four values make up
+// _NET_WM_STRUT.
+fn strut(area: Rect, height: u32) -> [u32; 12] {
+    let top: u32 = area.y.max(0) as u32 + height;
+    let start: u32 = area.x.max(0) as u32;
+    let end: u32 = start + area.w.max(1) - 1;
+    [0, 0, top, 0, 0, 0, 0, 0, start, end, 0, 0]
+}
+
+// Config colours are RGBA, X wants the RGB of TrueColor visuals.
+fn pixel(color: u32) -> u32 {
+    color >> 8
+}
+
+// whether the pixel values of a visual are 8 bit red, green and blue channels
+fn is_rgb24(visual: &Visualtype) -> bool {
+    visual.class == VisualClass::TRUE_COLOR
+        && visual.red_mask == 0xff0000
+        && visual.green_mask == 0xff00
+        && visual.blue_mask == 0xff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn struts_reserve_the_bar_on_its_monitor() {
+        let strut: [u32; 12] = strut(Rect::new(1920, 0, 1280, 1024), 20);
+        assert_eq!(strut[..4], [0, 0, 20, 0]);
+        assert_eq!((strut[8], strut[9]), (1920, 3199));
+        assert_eq!(super::strut(Rect::new(0, 1080, 1920, 1080), 20)[2], 1100);
+    }
+
+    #[test]
+    fn only_rgb24_visuals_take_the_colours_as_they_are() {
+        let visual = |class: VisualClass, red_mask: u32| -> Visualtype {
+            Visualtype {
+                visual_id: 0x21,
+                class,
+                bits_per_rgb_value: 8,
+                colormap_entries: 256,
+                red_mask,
+                green_mask: 0xff00,
+                blue_mask: 0xff,
+            }
+        };
+        assert!(is_rgb24(&visual(VisualClass::TRUE_COLOR, 0xff0000)));
+        assert!(!is_rgb24(&visual(VisualClass::TRUE_COLOR, 0xf800)));
+        assert!(!is_rgb24(&visual(VisualClass::PSEUDO_COLOR, 0xff0000)));
+        assert_eq!(pixel(0x336699ff), 0x336699);
+    }
+
+    #[test]
+    fn text_is_drawn_in_latin1() {
+        assert_eq!(latin1("Caf\u{e9} \u{2013} vim"), b"Caf\xe9 ? vim");
+        assert_eq!(latin1(&"x".repeat(300)).len(), 255);
+    }
+}
@@ -0,0 +1,156 @@
+// shared with the window manager, which also builds the replies
+#[allow(dead_code)]
+#[path = "../ipc/protocol.rs"]
+mod protocol;
+
+use protocol::{socket_path, Command, EventKind, Reply, SOCKET_VARIABLE};
+use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process;
+
+const USAGE: &str = "Usage: hippowmc [OPTIONS] <COMMAND>
+
+Commands:
+  action <NAME>         run an action keys can be bound to, e.g. kill or nextlayout
+  workspace <NAME>      focus a workspace
+  move <WORKSPACE>      move the focused window to a workspace
+  spawn <COMMAND>...    run a command
+  ratio <RATIO>         set the size of the main area, between 0 and 1
+  subscribe [KIND]...   print events as they happen, of the kinds workspace, window,
+                        layout, monitor and config, or of all kinds if none are given
+  get_tree              print the outputs, workspaces and windows
+  get_workspaces        print the workspaces with their windows
+  get_outputs           print the outputs and the workspaces they show
+
+Options:
+  -s, --socket <PATH>   socket to connect to, defaults to $HIPPOWM_SOCKET
+  -h, --help            print this help and exit";
+
+// Sends one command to the window manager and prints its reply. Exits with a failure
+// code if the command failed. Subscriptions go on to print every event line, the answers
+// of queries are printed on their own, indented.
+fn main() {
+    let mut arguments = env::args().skip(1).peekable();
+    let mut socket: Option<PathBuf> = None;
+    while let Some(flag) = arguments.next_if(|argument| argument.starts_with('-')) {
+        match flag.as_str() {
+            "-s" | "--socket" => match arguments.next() {
+                Some(path) => socket = Some(PathBuf::from(path)),
+                None => fail("Missing value for --socket"),
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ => fail(&format!("Unknown argument '{}'\n\n{}", flag, USAGE)),
+        }
+    }
+    let command: Command =
+        parse_command(&arguments.collect::<Vec<String>>()).unwrap_or_else(|error| fail(&error));
+
+    // the window manager exports the socket to everything it spawns
+    let socket: PathBuf = socket
+        .or_else(|| env::var_os(SOCKET_VARIABLE).map(PathBuf::from))
+        .unwrap_or_else(|| socket_path(&env::var("DISPLAY").unwrap_or_default()));
+    let mut stream: BufReader<UnixStream> =
+        send(&socket, &command).unwrap_or_else(|error| fail(&error));
+    let reply: String = read_line(&mut stream).unwrap_or_else(|error| fail(&error));
+    match serde_json::from_str::<Reply>(&reply) {
+        Ok(Reply {
+            success: true,
+            data: Some(data),
+            ..
+        }) => match serde_json::to_string_pretty(&data) {
+            Ok(data) => println!("{}", data),
+            Err(_) => println!("{}", reply),
+        },
+        Ok(parsed) if parsed.success => println!("{}", reply),
+        _ => {
+            println!("{}", reply);
+            process::exit(libc::EXIT_FAILURE);
+        }
+    }
+    if let Command::Subscribe { .. } = command {
+        // the window manager closes the stream when it exits or restarts
+        for line in stream.lines() {
+            match line {
+                Ok(line) => println!("{}", line),
+                Err(error) => fail(&format!("Could not read an event: {}", error)),
+            }
+        }
+    }
+}
+
+fn parse_command(arguments: &[String]) -> Result<Command, String> {
+    let (name, rest) = match arguments.split_first() {
+        Some((name, rest)) => (name.as_str(), rest),
+        None => return Err(format!("Missing command\n\n{}", USAGE)),
+    };
+    // every command but spawn, subscribe and the queries takes exactly one argument
+    let argument = || -> Result<String, String> {
+        match rest {
+            [argument] => Ok(argument.clone()),
+            _ => Err(format!("{} takes one argument\n\n{}", name, USAGE)),
+        }
+    };
+    match name {
+        "action" => Ok(Command::Action { name: argument()? }),
+        "workspace" => Ok(Command::FocusWorkspace {
+            workspace: argument()?,
+        }),
+        "move" => Ok(Command::MoveWindow {
+            workspace: argument()?,
+        }),
+        "spawn" if !rest.is_empty() => Ok(Command::Spawn {
+            program: rest.join(" "),
+        }),
+        "spawn" => Err(format!("spawn needs a command\n\n{}", USAGE)),
+        "ratio" => match argument()?.parse::<f32>() {
+            Ok(ratio) => Ok(Command::SetRatio { ratio }),
+            Err(error) => Err(format!("Invalid ratio: {}", error)),
+        },
+        "subscribe" => Ok(Command::Subscribe {
+            events: rest
+                .iter()
+                .map(|kind| {
+                    serde_json::from_value::<EventKind>(serde_json::Value::String(kind.clone()))
+                        .map_err(|_| format!("Unknown event kind '{}'\n\n{}", kind, USAGE))
+                })
+                .collect::<Result<Vec<EventKind>, String>>()?,
+        }),
+        "get_tree" | "get_workspaces" | "get_outputs" if !rest.is_empty() => {
+            Err(format!("{} takes no arguments\n\n{}", name, USAGE))
+        }
+        "get_tree" => Ok(Command::GetTree),
+        "get_workspaces" => Ok(Command::GetWorkspaces),
+        "get_outputs" => Ok(Command::GetOutputs),
+        _ => Err(format!("Unknown command '{}'\n\n{}", name, USAGE)),
+    }
+}
+
+// Sends the command, the window manager replies on the returned stream.
+fn send(socket: &PathBuf, command: &Command) -> Result<BufReader<UnixStream>, String> {
+    let mut stream: UnixStream = UnixStream::connect(socket)
+        .map_err(|error| format!("Could not connect to {}: {}", socket.display(), error))?;
+    let json: String = serde_json::to_string(command).map_err(|error| error.to_string())?;
+    writeln!(stream, "{}", json)
+        .map_err(|error| format!("Could not send the command: {}", error))?;
+    Ok(BufReader::new(stream))
+}
+
+// Waits for the line the window manager replies with.
+fn read_line(stream: &mut BufReader<UnixStream>) -> Result<String, String> {
+    let mut reply: String = String::new();
+    stream
+        .read_line(&mut reply)
+        .map_err(|error| format!("Could not read the reply: {}", error))?;
+    Ok(reply.trim_end().to_string())
+}
+
+// Reports the error and exits with a failure code.
+fn fail(error: &str) -> ! {
+    eprintln!("{}", error);
+    process::exit(libc::EXIT_FAILURE)
+}
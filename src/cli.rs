@@ -0,0 +1,161 @@
+use std::env;
+use std::fmt;
+
+// The window manager implementations which can be started.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Penrose,
+    Xlib,
+}
+
+impl Backend {
+    fn parse(name: &str) -> Result<Backend, String> {
+        match name.to_lowercase().as_str() {
+            "penrose" => Ok(Backend::Penrose),
+            "xlib" | "x" => Ok(Backend::Xlib),
+            _ => Err(format!(
+                "Unknown backend '{}', expected penrose or xlib",
+                name
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Backend::Penrose => write!(f, "penrose"),
+            Backend::Xlib => write!(f, "xlib"),
+        }
+    }
+}
+
+// What the user asked for on the command line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Run(Args),
+    Version,
+    Help,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Args {
+    pub backend: Backend,
+    // X display to connect to, e.g. ":1", None uses $DISPLAY
+    pub display: Option<String>,
+    // config file to load instead of the default confy location
+    pub config: Option<String>,
+}
+
+pub const USAGE: &str = "Usage: hippowm [OPTIONS]
+
+Options:
+  -b, --backend <penrose|xlib>  window manager implementation to run (env: HIPPOWM_BACKEND)
+  -d, --display <DISPLAY>       X display to connect to, defaults to $DISPLAY
+  -c, --config <PATH>           config file to use instead of the default one
+  -V, --version                 print the version and exit
+  -h, --help                    print this help and exit";
+
+// Parses the arguments without the program name. If no backend is given,
+// HIPPOWM_BACKEND is used and the xlib backend is the default.
+pub fn parse<I: IntoIterator<Item = String>>(arguments: I) -> Result<Command, String> {
+    let mut backend: Option<Backend> = None;
+    let mut display: Option<String> = None;
+    let mut config: Option<String> = None;
+
+    let mut arguments = arguments.into_iter();
+    while let Some(argument) = arguments.next() {
+        // both "--flag value" and "--flag=value" are accepted
+        let (flag, inline_value): (&str, Option<String>) = match argument.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+            _ => (argument.as_str(), None),
+        };
+        let mut value = |name: &str| -> Result<String, String> {
+            inline_value
+                .clone()
+                .or_else(|| arguments.next())
+                .ok_or(format!("Missing value for {}", name))
+        };
+
+        match flag {
+            "-b" | "--backend" => backend = Some(Backend::parse(&value(flag)?)?),
+            "-d" | "--display" => display = Some(value(flag)?),
+            "-c" | "--config" => config = Some(value(flag)?),
+            "-V" | "--version" => return Ok(Command::Version),
+            "-h" | "--help" => return Ok(Command::Help),
+            _ => return Err(format!("Unknown argument '{}'\n\n{}", argument, USAGE)),
+        }
+    }
+
+    let backend: Backend = match backend {
+        Some(backend) => backend,
+        None => match env::var("HIPPOWM_BACKEND") {
+            Ok(name) if !name.is_empty() => Backend::parse(&name)?,
+            _ => Backend::Xlib,
+        },
+    };
+
+    Ok(Command::Run(Args {
+        backend,
+        display,
+        config,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(arguments: &[&str]) -> Result<Command, String> {
+        parse(arguments.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn options_take_separate_and_inline_values() {
+        assert_eq!(
+            parse_args(&["--backend", "penrose", "-c", "b.yml", "--display=:1"]),
+            Ok(Command::Run(Args {
+                backend: Backend::Penrose,
+                display: Some(":1".to_string()),
+                config: Some("b.yml".to_string()),
+            }))
+        );
+        assert_eq!(
+            parse_args(&["--config=a.yml", "-b", "X"]),
+            Ok(Command::Run(Args {
+                backend: Backend::Xlib,
+                display: None,
+                config: Some("a.yml".to_string()),
+            }))
+        );
+    }
+
+    #[test]
+    fn unknown_backends_and_arguments_are_rejected() {
+        assert!(parse_args(&["--backend", "wayland"])
+            .unwrap_err()
+            .starts_with("Unknown backend 'wayland'"));
+        assert!(parse_args(&["-b", "xlib", "--verbose"])
+            .unwrap_err()
+            .starts_with("Unknown argument '--verbose'"));
+    }
+
+    #[test]
+    fn options_need_a_value() {
+        assert_eq!(
+            parse_args(&["-b", "xlib", "--config"]),
+            Err("Missing value for --config".to_string())
+        );
+        assert_eq!(
+            parse_args(&["--display"]),
+            Err("Missing value for --display".to_string())
+        );
+    }
+
+    #[test]
+    fn help_and_version_stop_parsing() {
+        assert_eq!(parse_args(&["--help", "--bogus"]), Ok(Command::Help));
+        assert_eq!(parse_args(&["-b", "xlib", "-h"]), Ok(Command::Help));
+        assert_eq!(parse_args(&["-V"]), Ok(Command::Version));
+    }
+}
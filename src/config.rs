@@ -1,4 +1,6 @@
+use crate::rules::Rule;
 use serde::{Deserialize, Serialize};
+use std::path::Path;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Config {
@@ -18,6 +20,38 @@ pub struct Config {
     pub x_actions: Vec<Action>,
     pub workspaces: Vec<String>,
     pub auto_start: Vec<String>,
+    // milliseconds a window gets to close itself before its process is killed, 0 disables this
+    #[serde(default)]
+    pub force_kill_timeout: u64,
+    // shrink tiled windows to the resize increments they ask for, e.g. terminals to whole cells
+    #[serde(default)]
+    pub honor_size_increments: bool,
+    // border colour of windows which demand attention
+    #[serde(default = "default_urgent_border")]
+    pub urgent_border: u32,
+    // rules applied to new windows, e.g. to put them on a workspace or float them
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    #[serde(default)]
+    pub focus: FocusModel,
+    // cursor theme and size, XCURSOR_THEME/XCURSOR_SIZE or Xresources are used if unset
+    #[serde(default)]
+    pub cursor_theme: Option<String>,
+    #[serde(default)]
+    pub cursor_size: Option<u32>,
+    // a bar on top of the first monitor with the workspaces, the layout and the focused
+    // window, it reserves its own space, so top_gaps can stay 0
+    #[serde(default)]
+    pub bar: bool,
+    #[serde(default = "default_bar_height")]
+    pub bar_height: u32,
+    // X core font of the bar, e.g. "fixed" or "-misc-fixed-medium-r-normal--13-*"
+    #[serde(default = "default_bar_font")]
+    pub bar_font: String,
+    #[serde(default = "default_bar_background")]
+    pub bar_background: u32,
+    #[serde(default = "default_bar_foreground")]
+    pub bar_foreground: u32,
 }
 
 impl Default for Config {
@@ -40,10 +74,16 @@ impl Default for Config {
             commands: get_commands(),
             window_commands: vec![],
             x_command: vec![],
-            actions: vec![Action {
-                bind: "M-S-q".into(),
-                action: "kill".into(),
-            }],
+            actions: vec![
+                Action {
+                    bind: "M-S-q".into(),
+                    action: "kill".into(),
+                },
+                Action {
+                    bind: "M-S-r".into(),
+                    action: "Restart".into(),
+                },
+            ],
             window_actions: vec![],
             x_actions: vec![
                 Action {
@@ -109,11 +149,39 @@ impl Default for Config {
             ],
             workspaces,
             auto_start: vec![],
+            force_kill_timeout: 0,
+            honor_size_increments: false,
+            urgent_border: default_urgent_border(),
+            rules: vec![],
+            focus: FocusModel::default(),
+            cursor_theme: None,
+            cursor_size: None,
+            bar: false,
+            bar_height: default_bar_height(),
+            bar_font: default_bar_font(),
+            bar_background: default_bar_background(),
+            bar_foreground: default_bar_foreground(),
         };
         confy::store("hippowm", Some("config"), config).unwrap();
         confy::load("hippowm", Some("config")).unwrap()
     }
 }
+fn default_urgent_border() -> u32 {
+    0xff5555ff
+}
+fn default_bar_height() -> u32 {
+    20
+}
+fn default_bar_font() -> String {
+    "fixed".into()
+}
+fn default_bar_background() -> u32 {
+    0x333333ff
+}
+fn default_bar_foreground() -> u32 {
+    0xffffffff
+}
+
 pub fn get_commands() -> Vec<Command> {
     return vec![
         Command {
@@ -127,6 +195,19 @@ pub fn get_commands() -> Vec<Command> {
     ];
 }
 
+// How clients get focus, written as "click", "follows_mouse" or "sloppy" in the config.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FocusModel {
+    // clicking a client focuses it, the click is passed on to the client
+    Click,
+    // the client under the pointer has focus, also when the layout moved it there
+    #[default]
+    FollowsMouse,
+    // only moving the pointer into a client focuses it
+    Sloppy,
+}
+
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Action {
     pub bind: String,
@@ -139,6 +220,14 @@ pub struct Command {
     pub command: String,
 }
 
+// load configuration from the given file, which has to exist, confy would create it otherwise
+pub fn get_config_from(path: &str) -> Result<Config, String> {
+    if !Path::new(path).is_file() {
+        return Err(format!("Could not load config {}: no such file", path));
+    }
+    confy::load_path(path).map_err(|error| format!("Could not load config {}: {}", path, error))
+}
+
 // load configuration or create default one, if one does not already exist
 pub fn get_config() -> Config {
     confy::load("hippowm", Some("config")).unwrap_or_else(|_| {
@@ -0,0 +1,304 @@
+use x11rb::connection::Connection;
+use x11rb::errors::{ConnectionError, ReplyError};
+use x11rb::properties::{WmHints, WmHintsState, WmSizeHints};
+use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt, Window};
+
+// Size constraints a client sets in WM_NORMAL_HINTS (ICCCM 4.1.2.3). All sizes are
+// without the border.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SizeHints {
+    pub min: Option<(u32, u32)>,
+    pub max: Option<(u32, u32)>,
+    pub base: Option<(u32, u32)>,
+    pub increment: Option<(u32, u32)>,
+    // minimum and maximum ratio of width to height
+    pub aspect: Option<(f32, f32)>,
+}
+
+impl SizeHints {
+    pub fn from_wm_size_hints(hints: &WmSizeHints) -> Self {
+        SizeHints::new(
+            hints.min_size,
+            hints.max_size,
+            hints.base_size,
+            hints.size_increment,
+            hints.aspect.map(|(min, max)| {
+                (
+                    (min.numerator, min.denominator),
+                    (max.numerator, max.denominator),
+                )
+            }),
+        )
+    }
+
+    // The fields of WM_NORMAL_HINTS, the aspect ratios as numerator and denominator. Penrose
+    // reads them with another version of x11rb, so they are passed on one by one.
+    pub fn new(
+        min: Option<(i32, i32)>,
+        max: Option<(i32, i32)>,
+        base: Option<(i32, i32)>,
+        increment: Option<(i32, i32)>,
+        aspect: Option<((i32, i32), (i32, i32))>,
+    ) -> Self {
+        let aspect: Option<(f32, f32)> = match aspect {
+            Some(((min_n, min_d), (max_n, max_d))) if min_d > 0 && max_d > 0 => {
+                Some((min_n as f32 / min_d as f32, max_n as f32 / max_d as f32))
+            }
+            _ => None,
+        };
+        SizeHints {
+            min: positive(min),
+            max: positive(max),
+            base: positive(base),
+            increment: positive(increment),
+            aspect,
+        }
+    }
+
+    // a client which can not be resized is floated instead of tiled
+    pub fn is_fixed(&self) -> bool {
+        matches!((self.min, self.max), (Some(min), Some(max)) if min == max)
+    }
+
+    // Adjusts a size to every hint, used for floating clients. This follows ICCCM 4.1.2.3:
+    // the base size (or the minimum size if there is no base) is left out when applying
+    // the aspect ratio and the increments.
+    pub fn constrain(&self, w: u32, h: u32) -> (u32, u32) {
+        let (base_w, base_h) = self.base.or(self.min).unwrap_or((0, 0));
+        let mut w: u32 = w.saturating_sub(base_w);
+        let mut h: u32 = h.saturating_sub(base_h);
+
+        if let Some((min_aspect, max_aspect)) = self.aspect {
+            if w > 0 && h > 0 {
+                let ratio: f32 = w as f32 / h as f32;
+                if max_aspect > 0.0 && ratio > max_aspect {
+                    w = (h as f32 * max_aspect + 0.5) as u32;
+                } else if min_aspect > 0.0 && ratio < min_aspect {
+                    h = (w as f32 / min_aspect + 0.5) as u32;
+                }
+            }
+        }
+        if let Some((inc_w, inc_h)) = self.increment {
+            w -= w % inc_w;
+            h -= h % inc_h;
+        }
+
+        let (mut w, mut h) = (w + base_w, h + base_h);
+        if let Some((min_w, min_h)) = self.min {
+            w = w.max(min_w);
+            h = h.max(min_h);
+        }
+        if let Some((max_w, max_h)) = self.max {
+            w = w.min(max_w);
+            h = h.min(max_h);
+        }
+        (w.max(1), h.max(1))
+    }
+
+    // Rounds a size down to the resize increments only, used for tiled clients which must
+    // stay inside their tile no matter what their other hints say.
+    pub fn apply_increments(&self, w: u32, h: u32) -> (u32, u32) {
+        let (inc_w, inc_h) = match self.increment {
+            Some(increment) => increment,
+            None => return (w, h),
+        };
+        let (base_w, base_h) = self.base.or(self.min).unwrap_or((0, 0));
+        if w <= base_w || h <= base_h {
+            return (w, h);
+        }
+        (w - (w - base_w) % inc_w, h - (h - base_h) % inc_h)
+    }
+}
+
+// How a client wants to receive keyboard focus, derived from the input field of WM_HINTS
+// and WM_TAKE_FOCUS in WM_PROTOCOLS (ICCCM 4.1.7).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InputModel {
+    // never takes focus
+    NoInput,
+    // focus is set by the window manager
+    #[default]
+    Passive,
+    // focus is set by the window manager, the client is told about it with WM_TAKE_FOCUS
+    LocallyActive,
+    // the client decides itself where focus goes when it gets WM_TAKE_FOCUS
+    GloballyActive,
+}
+
+impl InputModel {
+    pub fn new(input: bool, take_focus: bool) -> Self {
+        match (input, take_focus) {
+            (false, false) => InputModel::NoInput,
+            (true, false) => InputModel::Passive,
+            (true, true) => InputModel::LocallyActive,
+            (false, true) => InputModel::GloballyActive,
+        }
+    }
+
+    // whether the window manager gives focus to the client with SetInputFocus
+    pub fn set_focus(&self) -> bool {
+        matches!(self, InputModel::Passive | InputModel::LocallyActive)
+    }
+
+    // whether the client has to be sent WM_TAKE_FOCUS when it is focused
+    pub fn take_focus(&self) -> bool {
+        matches!(self, InputModel::LocallyActive | InputModel::GloballyActive)
+    }
+}
+
+// The ICCCM hints of a client which are relevant to the window manager.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ClientHints {
+    pub size: SizeHints,
+    pub input: InputModel,
+    // the client demands attention
+    pub urgent: bool,
+    // the client asked to start iconified
+    pub iconic: bool,
+}
+
+impl ClientHints {
+    // input is the field of WM_HINTS, take_focus whether WM_PROTOCOLS has WM_TAKE_FOCUS
+    pub fn new(
+        size: SizeHints,
+        input: Option<bool>,
+        take_focus: bool,
+        urgent: bool,
+        iconic: bool,
+    ) -> Self {
+        ClientHints {
+            size,
+            // clients which do not set the input field are given focus
+            input: InputModel::new(input.unwrap_or(true), take_focus),
+            urgent,
+            iconic,
+        }
+    }
+}
+
+// Fetches WM_NORMAL_HINTS, WM_HINTS and WM_PROTOCOLS of a client in one round trip.
+// Properties which are missing or malformed count as not set.
+pub fn get_client_hints<C: Connection>(
+    conn: &C,
+    window: Window,
+    wm_protocols: Atom,
+    wm_take_focus: Atom,
+) -> Result<ClientHints, ReplyError> {
+    let size_cookie = WmSizeHints::get_normal_hints(conn, window)?;
+    let hints_cookie = WmHints::get(conn, window)?;
+    let protocols_cookie =
+        conn.get_property(false, window, wm_protocols, AtomEnum::ATOM, 0, u32::MAX)?;
+
+    let size: SizeHints = match ignore_malformed(size_cookie.reply())? {
+        Some(hints) => SizeHints::from_wm_size_hints(&hints),
+        None => SizeHints::default(),
+    };
+    let wm_hints: WmHints = ignore_malformed(hints_cookie.reply())?.unwrap_or_default();
+    let take_focus: bool = protocols_cookie
+        .reply()?
+        .value32()
+        .map(|mut atoms| atoms.any(|atom| atom == wm_take_focus))
+        .unwrap_or(false);
+
+    Ok(ClientHints::new(
+        size,
+        wm_hints.input,
+        take_focus,
+        wm_hints.urgent,
+        matches!(wm_hints.initial_state, Some(WmHintsState::Iconic)),
+    ))
+}
+
+fn ignore_malformed<T>(reply: Result<Option<T>, ReplyError>) -> Result<Option<T>, ReplyError> {
+    match reply {
+        Err(ReplyError::ConnectionError(ConnectionError::ParseError(_))) => Ok(None),
+        reply => reply,
+    }
+}
+
+// drops sizes which are zero or negative, as those mean the hint is unset
+fn positive(size: Option<(i32, i32)>) -> Option<(u32, u32)> {
+    match size {
+        Some((w, h)) if w > 0 && h > 0 => Some((w as u32, h as u32)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terminal() -> SizeHints {
+        SizeHints {
+            min: Some((20, 30)),
+            base: Some((4, 4)),
+            increment: Some((8, 16)),
+            ..SizeHints::default()
+        }
+    }
+
+    #[test]
+    fn no_hints_keep_the_size() {
+        let hints: SizeHints = SizeHints::default();
+        assert_eq!(hints.constrain(640, 480), (640, 480));
+        assert_eq!(hints.apply_increments(640, 480), (640, 480));
+        assert!(!hints.is_fixed());
+    }
+
+    #[test]
+    fn min_and_max_are_enforced() {
+        let hints: SizeHints = SizeHints {
+            min: Some((100, 50)),
+            max: Some((400, 300)),
+            ..SizeHints::default()
+        };
+        assert_eq!(hints.constrain(10, 10), (100, 50));
+        assert_eq!(hints.constrain(1000, 1000), (400, 300));
+        assert_eq!(hints.constrain(200, 200), (200, 200));
+    }
+
+    #[test]
+    fn equal_min_and_max_is_fixed() {
+        let hints: SizeHints = SizeHints {
+            min: Some((300, 200)),
+            max: Some((300, 200)),
+            ..SizeHints::default()
+        };
+        assert!(hints.is_fixed());
+        assert_eq!(hints.constrain(640, 480), (300, 200));
+    }
+
+    #[test]
+    fn increments_count_from_the_base_size() {
+        // 4 + 8 * 79 = 636 and 4 + 16 * 29 = 468
+        assert_eq!(terminal().constrain(640, 480), (636, 468));
+        assert_eq!(terminal().apply_increments(640, 480), (636, 468));
+    }
+
+    #[test]
+    fn tiled_increments_ignore_min_size() {
+        // a tile may be smaller than the minimum size, the client has to live with that
+        assert_eq!(terminal().apply_increments(12, 40), (12, 36));
+        assert_eq!(terminal().constrain(12, 40), (20, 36));
+    }
+
+    #[test]
+    fn aspect_ratio_is_kept() {
+        let hints: SizeHints = SizeHints {
+            aspect: Some((1.0, 2.0)),
+            ..SizeHints::default()
+        };
+        assert_eq!(hints.constrain(1000, 200), (400, 200));
+        assert_eq!(hints.constrain(200, 1000), (200, 200));
+    }
+
+    #[test]
+    fn input_models() {
+        assert!(!InputModel::new(false, false).set_focus());
+        assert!(!InputModel::new(false, false).take_focus());
+        assert!(InputModel::new(true, false).set_focus());
+        assert!(InputModel::new(true, true).take_focus());
+        assert!(!InputModel::new(false, true).set_focus());
+        assert!(InputModel::new(false, true).take_focus());
+    }
+}
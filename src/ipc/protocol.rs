@@ -0,0 +1,310 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::path::PathBuf;
+
+// Exported to the programs the window manager spawns, so hippowmc finds the socket.
+pub const SOCKET_VARIABLE: &str = "HIPPOWM_SOCKET";
+
+// A command sent to the socket, as one JSON object per line, e.g.
+// {"command": "focus_workspace", "workspace": "2"}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    // runs one of the actions keys can be bound to, e.g. "kill" or "nextlayout"
+    Action {
+        name: String,
+    },
+    FocusWorkspace {
+        workspace: String,
+    },
+    // moves the focused window to another workspace
+    MoveWindow {
+        workspace: String,
+    },
+    // a command line, run the same way as the commands of the config
+    Spawn {
+        program: String,
+    },
+    // size of the main area, between 0 and 1
+    SetRatio {
+        ratio: f32,
+    },
+    // turns the connection into a stream of events of the given kinds, all if none are
+    // given, which ends when the window manager exits or restarts
+    Subscribe {
+        #[serde(default)]
+        events: Vec<EventKind>,
+    },
+    // queries, answered with the whole Tree or a part of it as data
+    GetTree,
+    GetWorkspaces,
+    GetOutputs,
+}
+
+// The answer to every command, also one JSON object per line.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Reply {
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    // the answer to a query
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+impl Reply {
+    pub fn ok() -> Self {
+        Reply {
+            success: true,
+            error: None,
+            data: None,
+        }
+    }
+
+    pub fn error(error: impl Into<String>) -> Self {
+        Reply {
+            success: false,
+            error: Some(error.into()),
+            data: None,
+        }
+    }
+
+    pub fn data(data: &impl Serialize) -> Self {
+        match serde_json::to_value(data) {
+            Ok(data) => Reply {
+                data: Some(data),
+                ..Reply::ok()
+            },
+            Err(error) => Reply::error(format!("Could not serialise the answer: {}", error)),
+        }
+    }
+}
+
+// The state of the window manager as the queries return it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tree {
+    pub outputs: Vec<Output>,
+    pub workspaces: Vec<Workspace>,
+    // the window with input focus
+    pub focused: Option<u32>,
+}
+
+// A monitor, numbered from left to right.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Output {
+    pub index: usize,
+    // name of the RandR output, e.g. "HDMI-1", empty if it is unknown
+    pub name: String,
+    pub geometry: Geometry,
+    pub workspace: String,
+    pub focused: bool,
+}
+
+// Every workspace of the config, in the order of the config.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Workspace {
+    pub name: String,
+    pub layout: String,
+    // index of the output showing the workspace, if one does
+    pub output: Option<usize>,
+    pub focused: bool,
+    // clients in tiling order
+    pub clients: Vec<Client>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Client {
+    pub window: u32,
+    pub class: String,
+    pub instance: String,
+    pub title: String,
+    pub pid: Option<u32>,
+    pub floating: bool,
+    pub focused: bool,
+    pub geometry: Geometry,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Geometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Tree {
+    // The answer to one of the queries, which all return the tree or a part of it.
+    pub fn reply(&self, query: &Command) -> Reply {
+        match query {
+            Command::GetWorkspaces => Reply::data(&self.workspaces),
+            Command::GetOutputs => Reply::data(&self.outputs),
+            _ => Reply::data(self),
+        }
+    }
+}
+
+// Something which changed in the window manager, sent to subscribed clients as one JSON
+// object per line, e.g. {"event": "workspace_focused", "workspace": "2"}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    WorkspaceFocused { workspace: String },
+    WindowOpened { window: u32, title: String },
+    WindowClosed { window: u32 },
+    // window is None if no window has focus
+    WindowFocused { window: Option<u32>, title: String },
+    WindowTitleChanged { window: u32, title: String },
+    LayoutChanged { workspace: String, layout: String },
+    // monitors are numbered from left to right
+    MonitorFocused { monitor: usize },
+    MonitorsChanged { monitors: usize },
+    // the config is read again by the process a restart replaces us with, the stream
+    // ends right after this event
+    ConfigReloaded,
+}
+
+// What clients subscribe to, every kind covers the events starting with its name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Workspace,
+    Window,
+    Layout,
+    Monitor,
+    Config,
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::WorkspaceFocused { .. } => EventKind::Workspace,
+            Event::WindowOpened { .. }
+            | Event::WindowClosed { .. }
+            | Event::WindowFocused { .. }
+            | Event::WindowTitleChanged { .. } => EventKind::Window,
+            Event::LayoutChanged { .. } => EventKind::Layout,
+            Event::MonitorFocused { .. } | Event::MonitorsChanged { .. } => EventKind::Monitor,
+            Event::ConfigReloaded => EventKind::Config,
+        }
+    }
+}
+
+// Directory of the socket and of the state saved across restarts. XDG_RUNTIME_DIR can only
+// be accessed by the user, the directory of the user in the temp directory is used if it
+// is not set, which util::private_dir creates.
+pub fn runtime_dir() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => env::temp_dir().join(format!("hippowm-{}", unsafe { libc::getuid() })),
+    }
+}
+
+// The socket of the window manager running on the given display, in the runtime directory.
+pub fn socket_path(display: &str) -> PathBuf {
+    runtime_dir().join(format!("hippowm{}.sock", display.replace('/', "_")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_are_tagged_by_name() {
+        let command: Command =
+            serde_json::from_str(r#"{"command": "focus_workspace", "workspace": "2"}"#).unwrap();
+        assert_eq!(
+            command,
+            Command::FocusWorkspace {
+                workspace: "2".to_string()
+            }
+        );
+        assert_eq!(
+            serde_json::to_string(&Command::SetRatio { ratio: 0.5 }).unwrap(),
+            r#"{"command":"set_ratio","ratio":0.5}"#
+        );
+        assert!(serde_json::from_str::<Command>(r#"{"command": "reboot"}"#).is_err());
+    }
+
+    #[test]
+    fn subscriptions_default_to_all_events() {
+        let command: Command = serde_json::from_str(r#"{"command": "subscribe"}"#).unwrap();
+        assert_eq!(command, Command::Subscribe { events: vec![] });
+        let command: Command =
+            serde_json::from_str(r#"{"command": "subscribe", "events": ["window", "layout"]}"#)
+                .unwrap();
+        assert_eq!(
+            command,
+            Command::Subscribe {
+                events: vec![EventKind::Window, EventKind::Layout]
+            }
+        );
+    }
+
+    #[test]
+    fn events_are_tagged_by_name() {
+        let event: Event = Event::WindowFocused {
+            window: Some(4194310),
+            title: "vim".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"window_focused","window":4194310,"title":"vim"}"#
+        );
+        assert_eq!(event.kind(), EventKind::Window);
+        assert_eq!(
+            serde_json::to_string(&Event::ConfigReloaded).unwrap(),
+            r#"{"event":"config_reloaded"}"#
+        );
+    }
+
+    #[test]
+    fn errors_are_only_sent_on_failure() {
+        assert_eq!(
+            serde_json::to_string(&Reply::ok()).unwrap(),
+            r#"{"success":true}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&Reply::error("Unknown workspace 0")).unwrap(),
+            r#"{"success":false,"error":"Unknown workspace 0"}"#
+        );
+    }
+
+    #[test]
+    fn queries_answer_with_a_part_of_the_tree() {
+        let output: Output = Output {
+            index: 0,
+            name: "HDMI-1".to_string(),
+            geometry: Geometry {
+                x: 0,
+                y: 0,
+                width: 1920,
+                height: 1080,
+            },
+            workspace: "1".to_string(),
+            focused: true,
+        };
+        let tree: Tree = Tree {
+            outputs: vec![output.clone()],
+            workspaces: vec![Workspace {
+                name: "1".to_string(),
+                layout: "Side".to_string(),
+                output: Some(0),
+                focused: true,
+                clients: vec![],
+            }],
+            focused: None,
+        };
+        let command: Command = serde_json::from_str(r#"{"command": "get_outputs"}"#).unwrap();
+        assert_eq!(command, Command::GetOutputs);
+
+        let reply: Reply = tree.reply(&command);
+        assert!(reply.success);
+        let outputs: Vec<Output> = serde_json::from_value(reply.data.unwrap()).unwrap();
+        assert_eq!(outputs, vec![output]);
+        let reply: Reply = tree.reply(&Command::GetTree);
+        assert_eq!(
+            serde_json::from_value::<Tree>(reply.data.unwrap()).unwrap(),
+            tree
+        );
+    }
+}
@@ -0,0 +1,218 @@
+use crate::ipc::protocol::{socket_path, Command, Event, EventKind, Reply, SOCKET_VARIABLE};
+use crate::util::private_dir;
+use log::{info, warn};
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{Atom, ClientMessageEvent, ConnectionExt, EventMask, Window};
+use x11rb::rust_connection::RustConnection;
+
+// Type of the ClientMessage sent to a window of the window manager whenever a command
+// arrived. Both backends block in their event loop, so this wakes them up to take the
+// commands from the channel.
+pub const IPC_MESSAGE: &str = "_HIPPOWM_IPC";
+
+// A command of a client and the way back to it. The client waits until it was replied to.
+pub struct Request {
+    pub command: Command,
+    reply: Sender<Reply>,
+}
+
+impl Request {
+    pub fn reply(self, reply: Reply) {
+        // the client may have hung up already
+        let _ = self.reply.send(reply);
+    }
+}
+
+// Events which may wait for a subscriber. Subscribers which fall this far behind are
+// dropped, so they can not block the window manager.
+const QUEUE_LENGTH: usize = 256;
+
+// The events of a subscriber are written on a thread of its own, emitting only queues them.
+struct Subscriber {
+    queue: SyncSender<String>,
+    // empty for all events
+    kinds: Vec<EventKind>,
+}
+
+impl Subscriber {
+    fn wants(&self, kind: EventKind) -> bool {
+        self.kinds.is_empty() || self.kinds.contains(&kind)
+    }
+}
+
+// Sends events to the clients which subscribed to them. Clones share the subscribers,
+// a default one has none, e.g. when the socket could not be opened.
+#[derive(Clone, Default)]
+pub struct Events {
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+}
+
+impl Events {
+    pub fn emit(&self, event: Event) {
+        let mut subscribers = match self.subscribers.lock() {
+            Ok(subscribers) => subscribers,
+            Err(_) => return,
+        };
+        if subscribers.is_empty() {
+            return;
+        }
+        let json: String = match serde_json::to_string(&event) {
+            Ok(json) => json,
+            Err(error) => {
+                warn!("Could not serialise {:?}: {}", event, error);
+                return;
+            }
+        };
+        let kind: EventKind = event.kind();
+        // clients which hung up or stopped reading are dropped
+        subscribers.retain(|s| !s.wants(kind) || s.queue.try_send(json.clone()).is_ok());
+    }
+
+    // Writes the events to the client on the calling thread until the client hangs up,
+    // which drops the receiver and with it the subscriber.
+    fn subscribe(&self, mut stream: UnixStream, kinds: Vec<EventKind>) {
+        let (queue, events): (SyncSender<String>, Receiver<String>) =
+            mpsc::sync_channel(QUEUE_LENGTH);
+        match self.subscribers.lock() {
+            Ok(mut subscribers) => subscribers.push(Subscriber { queue, kinds }),
+            Err(_) => return,
+        }
+        for json in events {
+            if writeln!(stream, "{}", json).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+// Sends IPC_MESSAGE to the window of the window manager on a connection of its own.
+struct Waker {
+    conn: RustConnection,
+    window: Window,
+    atom: Atom,
+}
+
+impl Waker {
+    fn wake(&self) -> Result<(), Box<dyn Error>> {
+        let event = ClientMessageEvent::new(32, self.window, self.atom, [0u32; 5]);
+        // without an event mask the event goes to the connection which created the window
+        self.conn
+            .send_event(false, self.window, EventMask::NO_EVENT, event)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+}
+
+// Listens on the socket of the display and exports its path as HIPPOWM_SOCKET. Every
+// client is served on a thread of its own, their commands are sent to requests and the
+// events emitted on events go to the clients which subscribed to them. Both are created
+// by the caller, so it can hand them out before it knows whether the socket is available.
+// IPC_MESSAGE is sent to window, an unmapped window which the caller created, so no other
+// client sees it.
+pub fn listen(
+    display: Option<String>,
+    window: Window,
+    requests: Sender<Request>,
+    events: Events,
+) -> Result<(), Box<dyn Error>> {
+    let (conn, _) = x11rb::connect(display.as_deref())?;
+    let atom: Atom = conn
+        .intern_atom(false, IPC_MESSAGE.as_bytes())?
+        .reply()?
+        .atom;
+    let waker: Arc<Waker> = Arc::new(Waker { conn, window, atom });
+
+    let name: String = display
+        .or_else(|| env::var("DISPLAY").ok())
+        .unwrap_or_default();
+    private_dir()?;
+    let path: PathBuf = socket_path(&name);
+    // a socket nobody accepts on was left behind by a crash or by the process we replaced
+    // on a restart, one which is accepted on belongs to another instance
+    match UnixStream::connect(&path) {
+        Ok(_) => return Err(format!("{} is in use", path.display()).into()),
+        Err(error) if error.kind() == io::ErrorKind::ConnectionRefused => fs::remove_file(&path)?,
+        Err(_) => {}
+    }
+    let listener: UnixListener = UnixListener::bind(&path)?;
+    env::set_var(SOCKET_VARIABLE, &path);
+    info!("Listening for commands on {}", path.display());
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let requests: Sender<Request> = requests.clone();
+                    let waker: Arc<Waker> = waker.clone();
+                    let events: Events = events.clone();
+                    thread::spawn(move || serve(stream, requests, &waker, &events));
+                }
+                Err(error) => warn!("Could not accept a client: {}", error),
+            }
+        }
+    });
+    Ok(())
+}
+
+// Answers the commands of one client until it hangs up or subscribes.
+fn serve(stream: UnixStream, requests: Sender<Request>, waker: &Waker, events: &Events) {
+    if let Err(error) = serve_lines(stream, requests, waker, events) {
+        warn!("Lost a client: {}", error);
+    }
+}
+
+fn serve_lines(
+    stream: UnixStream,
+    requests: Sender<Request>,
+    waker: &Waker,
+    events: &Events,
+) -> io::Result<()> {
+    let reader: BufReader<UnixStream> = BufReader::new(stream.try_clone()?);
+    let mut writer: UnixStream = stream;
+    for line in reader.lines() {
+        let line: String = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply: Reply = match serde_json::from_str::<Command>(&line) {
+            // the window manager only emits events, so subscribing is done right here
+            Ok(Command::Subscribe { events: kinds }) => {
+                writeln!(writer, "{}", serde_json::to_string(&Reply::ok())?)?;
+                events.subscribe(writer, kinds);
+                return Ok(());
+            }
+            Ok(command) => dispatch(command, &requests, waker),
+            Err(error) => Reply::error(format!("Invalid command: {}", error)),
+        };
+        let json: String = serde_json::to_string(&reply)?;
+        writeln!(writer, "{}", json)?;
+    }
+    Ok(())
+}
+
+// Hands the command to the window manager and waits for its reply.
+fn dispatch(command: Command, requests: &Sender<Request>, waker: &Waker) -> Reply {
+    let (sender, receiver) = mpsc::channel();
+    let request: Request = Request {
+        command,
+        reply: sender,
+    };
+    if requests.send(request).is_err() {
+        return Reply::error("The window manager is shutting down");
+    }
+    if let Err(error) = waker.wake() {
+        return Reply::error(format!("Could not reach the window manager: {}", error));
+    }
+    receiver
+        .recv()
+        .unwrap_or_else(|_| Reply::error("The window manager did not reply"))
+}
@@ -0,0 +1,531 @@
+use crate::config::Config;
+use serde::{Deserialize, Serialize};
+
+// A rectangle on the screen, x and y may be negative on multi monitor setups.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, w: u32, h: u32) -> Self {
+        Rect { x, y, w, h }
+    }
+
+    // shrinks the rect by px on every side
+    pub fn shrink(&self, px: u32) -> Rect {
+        if self.w <= 2 * px || self.h <= 2 * px {
+            return *self;
+        }
+        Rect::new(
+            self.x + px as i32,
+            self.y + px as i32,
+            self.w - 2 * px,
+            self.h - 2 * px,
+        )
+    }
+
+    // a rect of the same size with the same center as other
+    pub fn centered_in(&self, other: Rect) -> Rect {
+        let x: i32 = other.x + (other.w as i32 - self.w as i32) / 2;
+        let y: i32 = other.y + (other.h as i32 - self.h as i32) / 2;
+        Rect::new(x, y, self.w, self.h)
+    }
+
+    // removes px from the top of the rect
+    pub fn reserve_top(&self, px: u32) -> Rect {
+        if px >= self.h {
+            return *self;
+        }
+        Rect::new(self.x, self.y + px as i32, self.w, self.h - px)
+    }
+
+    // Removes the space docks reserve from this screen. Struts are given relative to the
+    // edges of root, so only the part reaching into this screen counts, and only if the
+    // range of the strut overlaps the screen.
+    pub fn without_struts(&self, root: Rect, struts: &[Strut]) -> Rect {
+        // the screen in coordinates relative to root
+        let x0: u32 = (self.x - root.x).max(0) as u32;
+        let y0: u32 = (self.y - root.y).max(0) as u32;
+        let (x1, y1) = (x0 + self.w, y0 + self.h);
+
+        let (mut left, mut right, mut top, mut bottom) = (0, 0, 0, 0);
+        for s in struts {
+            if overlaps(s.left_start_y, s.left_end_y, y0, y1) {
+                left = left.max(s.left.saturating_sub(x0));
+            }
+            if overlaps(s.right_start_y, s.right_end_y, y0, y1) {
+                right = right.max(s.right.saturating_sub(root.w.saturating_sub(x1)));
+            }
+            if overlaps(s.top_start_x, s.top_end_x, x0, x1) {
+                top = top.max(s.top.saturating_sub(y0));
+            }
+            if overlaps(s.bottom_start_x, s.bottom_end_x, x0, x1) {
+                bottom = bottom.max(s.bottom.saturating_sub(root.h.saturating_sub(y1)));
+            }
+        }
+        // a dock covering the whole screen is ignored, nothing could be tiled otherwise
+        if left + right >= self.w || top + bottom >= self.h {
+            return *self;
+        }
+        Rect::new(
+            self.x + left as i32,
+            self.y + top as i32,
+            self.w - left - right,
+            self.h - top - bottom,
+        )
+    }
+
+    // splits the rect into n rows of equal height
+    fn as_rows(&self, n: u32) -> Vec<Rect> {
+        if n <= 1 {
+            return vec![*self];
+        }
+        let h: u32 = self.h / n;
+        (0..n)
+            .map(|i| Rect::new(self.x, self.y + (i * h) as i32, self.w, h))
+            .collect()
+    }
+
+    // splits the rect into n columns of equal width
+    fn as_columns(&self, n: u32) -> Vec<Rect> {
+        if n <= 1 {
+            return vec![*self];
+        }
+        let w: u32 = self.w / n;
+        (0..n)
+            .map(|i| Rect::new(self.x + (i * w) as i32, self.y, w, self.h))
+            .collect()
+    }
+
+    // splits the rect into a left part taking up ratio of the width and a right part
+    fn split_at_width_perc(&self, ratio: f32) -> (Rect, Rect) {
+        let w: u32 = (self.w as f32 * ratio) as u32;
+        (
+            Rect::new(self.x, self.y, w, self.h),
+            Rect::new(self.x + w as i32, self.y, self.w - w, self.h),
+        )
+    }
+
+    // splits the rect into a top part taking up ratio of the height and a bottom part
+    fn split_at_height_perc(&self, ratio: f32) -> (Rect, Rect) {
+        let h: u32 = (self.h as f32 * ratio) as u32;
+        (
+            Rect::new(self.x, self.y, self.w, h),
+            Rect::new(self.x, self.y + h as i32, self.w, self.h - h),
+        )
+    }
+}
+
+// whether the inclusive range start..=end overlaps the half open range from..to
+fn overlaps(start: u32, end: u32, from: u32, to: u32) -> bool {
+    start < to && end >= from
+}
+
+// Space a dock reserves along the edges of the root window, as set in _NET_WM_STRUT_PARTIAL.
+// Every side has a width and the range along that edge it covers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Strut {
+    pub left: u32,
+    pub right: u32,
+    pub top: u32,
+    pub bottom: u32,
+    pub left_start_y: u32,
+    pub left_end_y: u32,
+    pub right_start_y: u32,
+    pub right_end_y: u32,
+    pub top_start_x: u32,
+    pub top_end_x: u32,
+    pub bottom_start_x: u32,
+    pub bottom_end_x: u32,
+}
+
+impl Strut {
+    // reads the twelve values of _NET_WM_STRUT_PARTIAL
+    pub fn from_partial(values: &[u32]) -> Option<Strut> {
+        if values.len() < 12 {
+            return None;
+        }
+        Some(Strut {
+            left: values[0],
+            right: values[1],
+            top: values[2],
+            bottom: values[3],
+            left_start_y: values[4],
+            left_end_y: values[5],
+            right_start_y: values[6],
+            right_end_y: values[7],
+            top_start_x: values[8],
+            top_end_x: values[9],
+            bottom_start_x: values[10],
+            bottom_end_x: values[11],
+        })
+    }
+
+    // reads the four values of the older _NET_WM_STRUT, which covers whole edges
+    pub fn from_strut(values: &[u32]) -> Option<Strut> {
+        if values.len() < 4 {
+            return None;
+        }
+        Some(Strut {
+            left: values[0],
+            right: values[1],
+            top: values[2],
+            bottom: values[3],
+            left_end_y: u32::MAX,
+            right_end_y: u32::MAX,
+            top_end_x: u32::MAX,
+            bottom_end_x: u32::MAX,
+            ..Strut::default()
+        })
+    }
+}
+
+// The available layouts, in the same order as HippoWM::get_layouts of the penrose backend.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LayoutKind {
+    // main area on the left, stack on the right
+    Side,
+    // main area on the right, stack on the left
+    ReflectedSide,
+    // main area on top, stack on the bottom
+    Bottom,
+    // every window takes up the whole screen
+    Monocle,
+}
+
+// Gaps applied around the tiled windows, mirroring the penrose Gaps and ReserveTop transformers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Gaps {
+    pub outer: u32,
+    pub inner: u32,
+    pub top: u32,
+}
+
+impl Gaps {
+    pub fn from_config(config: &Config) -> Self {
+        Gaps {
+            outer: config.outer_gap,
+            inner: config.inner_gaps,
+            top: config.top_gaps,
+        }
+    }
+}
+
+// A backend independent tiling layout. It only computes positions, applying them is
+// up to the backend.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Layout {
+    pub kind: LayoutKind,
+    pub max_main: u32,
+    pub ratio: f32,
+    pub ratio_step: f32,
+}
+
+impl Layout {
+    pub fn new(kind: LayoutKind, config: &Config) -> Self {
+        Layout {
+            kind,
+            max_main: config.max_main,
+            ratio: config.ratio,
+            ratio_step: config.ratio_steps,
+        }
+    }
+
+    // creates all layouts, in the order they are cycled through
+    pub fn all(config: &Config) -> Vec<Layout> {
+        vec![
+            Layout::new(LayoutKind::Side, config),
+            Layout::new(LayoutKind::ReflectedSide, config),
+            Layout::new(LayoutKind::Bottom, config),
+            Layout::new(LayoutKind::Monocle, config),
+        ]
+    }
+
+    // same names as the corresponding penrose layouts
+    pub fn name(&self) -> &'static str {
+        match self.kind {
+            LayoutKind::Side => "Side",
+            LayoutKind::ReflectedSide => "Reflected<Side>",
+            LayoutKind::Bottom => "Bottom",
+            LayoutKind::Monocle => "Mono",
+        }
+    }
+
+    // changes the number of windows in the main area
+    pub fn inc_main(&mut self, n: i32) {
+        if n < 0 {
+            self.max_main = self.max_main.saturating_sub(n.unsigned_abs());
+        } else {
+            self.max_main += n as u32;
+        }
+    }
+
+    pub fn expand_main(&mut self) {
+        self.ratio = (self.ratio + self.ratio_step).min(1.0);
+    }
+
+    pub fn shrink_main(&mut self) {
+        self.ratio = (self.ratio - self.ratio_step).max(0.0);
+    }
+
+    // Computes the positions of n tiled windows on the given screen. The returned rects
+    // are in stack order, the first max_main of them make up the main area.
+    pub fn arrange(&self, n: usize, screen: Rect, gaps: Gaps) -> Vec<Rect> {
+        if n == 0 {
+            return vec![];
+        }
+        let area: Rect = screen.reserve_top(gaps.top).shrink(gaps.outer);
+        let n: u32 = n as u32;
+
+        let positions: Vec<Rect> = match self.kind {
+            LayoutKind::Side => self.side(n, area),
+            LayoutKind::ReflectedSide => reflect_horizontal(area, self.side(n, area)),
+            LayoutKind::Bottom => self.bottom(n, area),
+            LayoutKind::Monocle => vec![area; n as usize],
+        };
+        positions
+            .into_iter()
+            .map(|r| r.shrink(gaps.inner))
+            .collect()
+    }
+
+    // In each of these cases there is no split between a main area and a stack.
+    fn single_stack(&self, n: u32) -> bool {
+        n <= self.max_main || self.max_main == 0 || self.ratio >= 1.0 || self.ratio <= 0.0
+    }
+
+    fn side(&self, n: u32, area: Rect) -> Vec<Rect> {
+        if self.single_stack(n) {
+            return area.as_rows(n);
+        }
+        let (main, stack) = area.split_at_width_perc(self.ratio);
+        let mut positions: Vec<Rect> = main.as_rows(self.max_main);
+        positions.extend(stack.as_rows(n - self.max_main));
+        positions
+    }
+
+    fn bottom(&self, n: u32, area: Rect) -> Vec<Rect> {
+        if self.single_stack(n) {
+            return area.as_columns(n);
+        }
+        let (main, stack) = area.split_at_height_perc(self.ratio);
+        let mut positions: Vec<Rect> = main.as_columns(self.max_main);
+        positions.extend(stack.as_columns(n - self.max_main));
+        positions
+    }
+}
+
+// mirrors the positions along the vertical center line of the area
+fn reflect_horizontal(area: Rect, positions: Vec<Rect>) -> Vec<Rect> {
+    let offset: i32 = 2 * area.x + area.w as i32;
+    positions
+        .into_iter()
+        .map(|r| Rect::new(offset - r.x - r.w as i32, r.y, r.w, r.h))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCREEN: Rect = Rect {
+        x: 0,
+        y: 0,
+        w: 1000,
+        h: 600,
+    };
+
+    fn layout(kind: LayoutKind, max_main: u32, ratio: f32) -> Layout {
+        Layout {
+            kind,
+            max_main,
+            ratio,
+            ratio_step: 0.1,
+        }
+    }
+
+    #[test]
+    fn centered_rects_may_overhang() {
+        let dialog: Rect = Rect::new(0, 0, 200, 100);
+        assert_eq!(
+            dialog.centered_in(Rect::new(100, 100, 400, 300)),
+            Rect::new(200, 200, 200, 100)
+        );
+        assert_eq!(
+            dialog.centered_in(Rect::new(0, 0, 100, 50)),
+            Rect::new(-50, -25, 200, 100)
+        );
+    }
+
+    #[test]
+    fn no_windows_no_positions() {
+        let l: Layout = layout(LayoutKind::Side, 1, 0.5);
+        assert!(l.arrange(0, SCREEN, Gaps::default()).is_empty());
+    }
+
+    #[test]
+    fn single_window_fills_screen() {
+        for kind in [
+            LayoutKind::Side,
+            LayoutKind::ReflectedSide,
+            LayoutKind::Bottom,
+            LayoutKind::Monocle,
+        ] {
+            let positions: Vec<Rect> = layout(kind, 1, 0.5).arrange(1, SCREEN, Gaps::default());
+            assert_eq!(positions, vec![SCREEN], "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn side_splits_main_and_stack() {
+        let positions: Vec<Rect> =
+            layout(LayoutKind::Side, 1, 0.6).arrange(3, SCREEN, Gaps::default());
+        assert_eq!(
+            positions,
+            vec![
+                Rect::new(0, 0, 600, 600),
+                Rect::new(600, 0, 400, 300),
+                Rect::new(600, 300, 400, 300),
+            ]
+        );
+    }
+
+    #[test]
+    fn reflected_side_puts_main_on_the_right() {
+        let positions: Vec<Rect> =
+            layout(LayoutKind::ReflectedSide, 1, 0.6).arrange(2, SCREEN, Gaps::default());
+        assert_eq!(
+            positions,
+            vec![Rect::new(400, 0, 600, 600), Rect::new(0, 0, 400, 600)]
+        );
+    }
+
+    #[test]
+    fn bottom_puts_stack_below_main() {
+        let positions: Vec<Rect> =
+            layout(LayoutKind::Bottom, 1, 0.5).arrange(3, SCREEN, Gaps::default());
+        assert_eq!(
+            positions,
+            vec![
+                Rect::new(0, 0, 1000, 300),
+                Rect::new(0, 300, 500, 300),
+                Rect::new(500, 300, 500, 300),
+            ]
+        );
+    }
+
+    #[test]
+    fn monocle_stacks_everything() {
+        let positions: Vec<Rect> =
+            layout(LayoutKind::Monocle, 1, 0.5).arrange(3, SCREEN, Gaps::default());
+        assert_eq!(positions, vec![SCREEN; 3]);
+    }
+
+    #[test]
+    fn max_main_windows_share_main_area() {
+        let positions: Vec<Rect> =
+            layout(LayoutKind::Side, 2, 0.5).arrange(3, SCREEN, Gaps::default());
+        assert_eq!(
+            positions,
+            vec![
+                Rect::new(0, 0, 500, 300),
+                Rect::new(0, 300, 500, 300),
+                Rect::new(500, 0, 500, 600),
+            ]
+        );
+    }
+
+    #[test]
+    fn zero_max_main_is_a_single_stack() {
+        let positions: Vec<Rect> =
+            layout(LayoutKind::Side, 0, 0.5).arrange(2, SCREEN, Gaps::default());
+        assert_eq!(
+            positions,
+            vec![Rect::new(0, 0, 1000, 300), Rect::new(0, 300, 1000, 300)]
+        );
+    }
+
+    #[test]
+    fn gaps_are_applied() {
+        let gaps: Gaps = Gaps {
+            outer: 10,
+            inner: 5,
+            top: 20,
+        };
+        let positions: Vec<Rect> = layout(LayoutKind::Side, 1, 0.5).arrange(2, SCREEN, gaps);
+        // usable area is (10, 30, 980, 560), split into two columns of 490
+        assert_eq!(
+            positions,
+            vec![Rect::new(15, 35, 480, 550), Rect::new(505, 35, 480, 550)]
+        );
+    }
+
+    #[test]
+    fn offset_screens_are_respected() {
+        let screen: Rect = Rect::new(1920, 0, 1000, 600);
+        let positions: Vec<Rect> =
+            layout(LayoutKind::ReflectedSide, 1, 0.5).arrange(2, screen, Gaps::default());
+        assert_eq!(
+            positions,
+            vec![Rect::new(2420, 0, 500, 600), Rect::new(1920, 0, 500, 600)]
+        );
+    }
+
+    #[test]
+    fn struts_shrink_the_screen() {
+        // a bar at the top and one at the bottom, both across the whole screen
+        let top: Strut = Strut::from_partial(&[0, 0, 20, 0, 0, 0, 0, 0, 0, 999, 0, 0]).unwrap();
+        let bottom: Strut = Strut::from_strut(&[0, 0, 0, 30]).unwrap();
+        assert_eq!(
+            SCREEN.without_struts(SCREEN, &[top, bottom]),
+            Rect::new(0, 20, 1000, 550)
+        );
+    }
+
+    #[test]
+    fn struts_only_apply_to_their_monitor() {
+        // two monitors side by side, the bar only spans the right one
+        let root: Rect = Rect::new(0, 0, 2000, 600);
+        let left: Rect = Rect::new(0, 0, 1000, 600);
+        let right: Rect = Rect::new(1000, 0, 1000, 600);
+        let bar: Strut = Strut::from_partial(&[0, 0, 0, 25, 0, 0, 0, 0, 0, 0, 1000, 1999]).unwrap();
+        assert_eq!(left.without_struts(root, &[bar]), left);
+        assert_eq!(
+            right.without_struts(root, &[bar]),
+            Rect::new(1000, 0, 1000, 575)
+        );
+    }
+
+    #[test]
+    fn struts_are_relative_to_root() {
+        // a smaller monitor below a larger one does not touch the top edge of root
+        let root: Rect = Rect::new(0, 0, 1920, 1680);
+        let lower: Rect = Rect::new(0, 1080, 1000, 600);
+        let bar: Strut = Strut::from_strut(&[0, 0, 30, 0]).unwrap();
+        assert_eq!(lower.without_struts(root, &[bar]), lower);
+        // a left dock reaches into the lower monitor, the right one does not
+        let side: Strut = Strut::from_strut(&[40, 40, 0, 0]).unwrap();
+        assert_eq!(
+            lower.without_struts(root, &[side]),
+            Rect::new(40, 1080, 960, 600)
+        );
+    }
+
+    #[test]
+    fn layout_messages_are_clamped() {
+        let mut l: Layout = layout(LayoutKind::Side, 1, 0.95);
+        l.expand_main();
+        assert_eq!(l.ratio, 1.0);
+        l.ratio = 0.05;
+        l.shrink_main();
+        assert_eq!(l.ratio, 0.0);
+        l.inc_main(-3);
+        assert_eq!(l.max_main, 0);
+        l.inc_main(2);
+        assert_eq!(l.max_main, 2);
+    }
+}
@@ -1,33 +1,78 @@
-use config::get_config;
+use cli::{Backend, Command, USAGE};
+use config::{get_config, get_config_from, Config};
 use pen::hippowm::run;
 mod bar;
+mod cli;
 mod config;
+mod hints;
+mod ipc;
+mod layout;
 mod pen;
+mod restart;
+mod rules;
+mod util;
 use x::xwm::Xwm;
 mod x;
-use env_logger;
-use libc;
 use std::env::args;
+use std::path::PathBuf;
 use std::{env, process};
-use std::fmt::Pointer;
-use glib::property::PropertyGet;
 
 fn main() {
-    //let config = get_config();
-    //run(config).unwrap();
-    //This is for the x implementation
-    // Initialize logging with env_logger
+    let args = match cli::parse(args().skip(1)) {
+        Ok(Command::Run(args)) => args,
+        Ok(Command::Version) => {
+            println!("hippowm {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(error) => fail(&error),
+    };
 
-    env_logger::init();
+    let config: Config = match &args.config {
+        Some(path) => get_config_from(path).unwrap_or_else(|error| fail(&error)),
+        None => get_config(),
+    };
 
-    // Create WindowManager instance using pattern matching
-    let window_manager = match Xwm::create("") {
-        Ok(window_manager) => window_manager,
-        Err(error) => {
-            eprintln!("Failed to initialize window manager: {}", error);
-            process::exit(libc::EXIT_FAILURE)
+    match args.backend {
+        Backend::Penrose => {
+            // RustConn always connects to $DISPLAY
+            if let Some(display) = &args.display {
+                env::set_var("DISPLAY", display);
+            }
+            match run(config) {
+                Ok(()) => process::exit(util::exit_code()),
+                Err(error) => fail(&format!("Window manager exited with an error: {}", error)),
+            }
         }
-    };
+        Backend::Xlib => {
+            // Initialize logging with env_logger
+            env_logger::init();
+
+            // Create WindowManager instance using pattern matching
+            let display: &str = args.display.as_deref().unwrap_or("");
+            let window_manager = match Xwm::create(display, config) {
+                Ok(window_manager) => window_manager,
+                Err(error) => fail(&format!("Failed to initialize window manager: {}", error)),
+            };
+
+            let result = window_manager.borrow_mut().run();
+            let restart_file: Option<PathBuf> = window_manager.borrow().restart_file();
+            // gives the clients back to root and disconnects, which process::exit would skip
+            drop(window_manager);
+            match (result, restart_file) {
+                (Ok(()), Some(path)) => fail(&restart::exec(&path)),
+                (Ok(()), None) => process::exit(util::exit_code()),
+                (Err(error), _) => fail(&error.to_string()),
+            }
+        }
+    }
+}
 
-    window_manager.borrow().run();
+// Reports the error and exits with a failure code.
+fn fail(error: &str) -> ! {
+    eprintln!("{}", error);
+    process::exit(libc::EXIT_FAILURE)
 }
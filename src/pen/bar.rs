@@ -0,0 +1,70 @@
+use crate::bar::{Bar, Status, WorkspaceStatus};
+use crate::layout::Rect;
+use crate::pen::hints::SharedHints;
+use penrose::core::hooks::{EventHook, StateHook};
+use penrose::core::State;
+use penrose::pure::geometry::Rect as PRect;
+use penrose::x::event::PropertyEvent;
+use penrose::x::{XConnExt, XEvent};
+use penrose::x11rb::RustConn;
+use penrose::{Result, Xid};
+
+// What the bar shows, the urgency of clients comes from their tracked hints.
+fn status(state: &State<RustConn>, x: &RustConn, hints: &SharedHints) -> Status {
+    let cs = &state.client_set;
+    let urgent = |id: &Xid| -> bool { hints.borrow().get(*id).urgent };
+    let workspaces: Vec<WorkspaceStatus> = state
+        .config
+        .tags
+        .iter()
+        .filter_map(|tag| cs.workspace(tag))
+        .map(|ws| WorkspaceStatus {
+            name: ws.tag().to_string(),
+            focused: ws.tag() == cs.current_tag(),
+            occupied: ws.clients().next().is_some(),
+            urgent: ws.clients().any(urgent),
+        })
+        .collect();
+    // the bar is drawn on the first screen
+    let area: PRect = cs
+        .screens()
+        .find(|s| s.index() == 0)
+        .map_or(cs.current_screen().geometry(), |s| s.geometry());
+    Status {
+        workspaces,
+        layout: cs.current_workspace().layout_name(),
+        title: match cs.current_client() {
+            Some(id) => x.window_title(*id).unwrap_or_default(),
+            None => String::new(),
+        },
+        area: Rect::new(area.x as i32, area.y as i32, area.w, area.h),
+    }
+}
+
+// Updates the bar after every change of the state, penrose refreshes after each of them.
+pub fn refresh_hook(bar: Bar, hints: SharedHints) -> impl StateHook<RustConn> {
+    move |state: &mut State<RustConn>, x: &RustConn| -> Result<()> {
+        bar.update(status(state, x, &hints));
+        Ok(())
+    }
+}
+
+// Titles and urgency change without a refresh, so their property events update the bar.
+// The hook has to run after the one of the hints, which updates the urgency.
+pub fn event_hook(bar: Bar, hints: SharedHints) -> impl EventHook<RustConn> {
+    move |event: &XEvent, state: &mut State<RustConn>, x: &RustConn| -> Result<bool> {
+        match event {
+            XEvent::PropertyNotify(PropertyEvent {
+                id,
+                atom,
+                is_root: false,
+            }) if matches!(atom.as_str(), "WM_NAME" | "_NET_WM_NAME" | "WM_HINTS")
+                && state.client_set.contains(id) =>
+            {
+                bar.update(status(state, x, &hints));
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,124 @@
+use crate::layout::{Rect as LRect, Strut};
+use penrose::core::hooks::{EventHook, ManageHook};
+use penrose::core::layout::{Layout, LayoutTransformer};
+use penrose::core::State;
+use penrose::pure::geometry::Rect;
+use penrose::x::{event::PropertyEvent, ClientAttr, Prop, XConn, XConnExt, XEvent};
+use penrose::x11rb::RustConn;
+use penrose::{Result, Xid};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use tracing::info;
+
+// Penrose would tile dock windows like any other client. Instead they are taken out of
+// the client set again when they are managed, and the space they reserve is kept here
+// for the ReserveStruts transformer.
+#[derive(Default)]
+pub struct DockTracker {
+    struts: HashMap<Xid, Strut>,
+    // the area of the whole X screen, which struts are relative to
+    root: LRect,
+}
+
+pub type SharedDocks = Rc<RefCell<DockTracker>>;
+
+fn is_dock(id: Xid, x: &RustConn) -> bool {
+    match x.get_prop(id, "_NET_WM_WINDOW_TYPE") {
+        Ok(Some(Prop::Atom(types))) => types.iter().any(|t| t == "_NET_WM_WINDOW_TYPE_DOCK"),
+        _ => false,
+    }
+}
+
+// reads _NET_WM_STRUT_PARTIAL, falling back to the older _NET_WM_STRUT
+fn read_strut(id: Xid, x: &RustConn) -> Strut {
+    if let Ok(Some(Prop::Cardinal(values))) = x.get_prop(id, "_NET_WM_STRUT_PARTIAL") {
+        if let Some(strut) = Strut::from_partial(&values) {
+            return strut;
+        }
+    }
+    if let Ok(Some(Prop::Cardinal(values))) = x.get_prop(id, "_NET_WM_STRUT") {
+        if let Some(strut) = Strut::from_strut(&values) {
+            return strut;
+        }
+    }
+    Strut::default()
+}
+
+// Unmanages docks right after penrose managed them and maps them on their own. This
+// covers docks which are mapped later as well as the ones which exist on startup.
+pub fn manage_hook(docks: SharedDocks) -> impl ManageHook<RustConn> {
+    move |id: Xid, state: &mut State<RustConn>, x: &RustConn| -> Result<()> {
+        if !is_dock(id, x) {
+            return Ok(());
+        }
+        state.client_set.remove_client(&id);
+        // struts may change while the dock is mapped
+        x.set_client_attributes(id, &[ClientAttr::ClientEventMask])?;
+        x.map(id)?;
+
+        let root: Rect = x.client_geometry(x.root())?;
+        let strut: Strut = read_strut(id, x);
+        info!("Dock {} reserves {:?}", id, strut);
+        let mut docks = docks.borrow_mut();
+        docks.root = LRect::new(root.x as i32, root.y as i32, root.w, root.h);
+        docks.struts.insert(id, strut);
+        Ok(())
+    }
+}
+
+// Follows changes of the struts and forgets docks which are gone. Penrose refreshes the
+// layout itself after unmap and destroy events.
+pub fn event_hook(docks: SharedDocks) -> impl EventHook<RustConn> {
+    move |event: &XEvent, state: &mut State<RustConn>, x: &RustConn| -> Result<bool> {
+        match event {
+            XEvent::PropertyNotify(PropertyEvent { id, atom, .. })
+                if matches!(atom.as_str(), "_NET_WM_STRUT_PARTIAL" | "_NET_WM_STRUT")
+                    && docks.borrow().struts.contains_key(id) =>
+            {
+                let strut: Strut = read_strut(*id, x);
+                docks.borrow_mut().struts.insert(*id, strut);
+                x.refresh(state)?;
+            }
+            XEvent::UnmapNotify(id) | XEvent::Destroy(id) => {
+                docks.borrow_mut().struts.remove(id);
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+}
+
+// Takes the space reserved by docks off every screen before it is laid out.
+#[derive(Clone)]
+pub struct ReserveStruts {
+    layout: Box<dyn Layout>,
+    docks: SharedDocks,
+}
+
+impl ReserveStruts {
+    pub fn wrap(layout: Box<dyn Layout>, docks: SharedDocks) -> Box<dyn Layout> {
+        Box::new(Self { layout, docks })
+    }
+}
+
+impl LayoutTransformer for ReserveStruts {
+    fn transformed_name(&self) -> String {
+        self.layout.name()
+    }
+
+    fn inner_mut(&mut self) -> &mut Box<dyn Layout> {
+        &mut self.layout
+    }
+
+    fn transform_initial(&self, r: Rect) -> Rect {
+        let docks = self.docks.borrow();
+        if docks.struts.is_empty() {
+            return r;
+        }
+        let struts: Vec<Strut> = docks.struts.values().copied().collect();
+        let screen: LRect = LRect::new(r.x as i32, r.y as i32, r.w, r.h);
+        let area: LRect = screen.without_struts(docks.root, &struts);
+        Rect::new(area.x as u32, area.y as u32, area.w, area.h)
+    }
+}
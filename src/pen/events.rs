@@ -0,0 +1,119 @@
+use crate::ipc::protocol::Event;
+use crate::ipc::server::Events;
+use penrose::core::hooks::{EventHook, StateHook};
+use penrose::core::State;
+use penrose::x::event::PropertyEvent;
+use penrose::x::{XConnExt, XEvent};
+use penrose::x11rb::RustConn;
+use penrose::{Result, Xid};
+use std::collections::{BTreeMap, HashSet};
+
+// What subscribers were last told about, compared against the state after every refresh.
+#[derive(Default, PartialEq)]
+struct Snapshot {
+    workspace: String,
+    focused: Option<Xid>,
+    clients: HashSet<Xid>,
+    // layout of every workspace by tag
+    layouts: BTreeMap<String, String>,
+    screen: usize,
+    screens: usize,
+}
+
+impl Snapshot {
+    fn of(state: &State<RustConn>) -> Snapshot {
+        let cs = &state.client_set;
+        Snapshot {
+            workspace: cs.current_tag().to_string(),
+            focused: cs.current_client().copied(),
+            clients: cs.clients().copied().collect(),
+            layouts: cs
+                .workspaces()
+                .map(|ws| (ws.tag().to_string(), ws.layout_name()))
+                .collect(),
+            screen: cs.current_screen().index(),
+            screens: cs.screens().count(),
+        }
+    }
+}
+
+// Penrose refreshes after every change of its state, so the events are found by comparing
+// the state with the one of the last refresh. The first refresh only takes the snapshot.
+pub fn refresh_hook(events: Events) -> impl StateHook<RustConn> {
+    let mut last: Option<Snapshot> = None;
+    move |state: &mut State<RustConn>, x: &RustConn| -> Result<()> {
+        let now: Snapshot = Snapshot::of(state);
+        let old: Snapshot = match last.replace(now) {
+            Some(old) => old,
+            None => return Ok(()),
+        };
+        let now: &Snapshot = last.as_ref().unwrap();
+        if old == *now {
+            return Ok(());
+        }
+        // title of a client which may be gone already
+        let title = |id: Xid| x.window_title(id).unwrap_or_default();
+
+        if old.screens != now.screens {
+            events.emit(Event::MonitorsChanged {
+                monitors: now.screens,
+            });
+        }
+        if old.screen != now.screen {
+            events.emit(Event::MonitorFocused {
+                monitor: now.screen,
+            });
+        }
+        if old.workspace != now.workspace {
+            events.emit(Event::WorkspaceFocused {
+                workspace: now.workspace.clone(),
+            });
+        }
+        for id in old.clients.difference(&now.clients) {
+            events.emit(Event::WindowClosed { window: **id });
+        }
+        for id in now.clients.difference(&old.clients) {
+            events.emit(Event::WindowOpened {
+                window: **id,
+                title: title(*id),
+            });
+        }
+        if old.focused != now.focused {
+            events.emit(Event::WindowFocused {
+                window: now.focused.map(|id| *id),
+                title: now.focused.map(title).unwrap_or_default(),
+            });
+        }
+        for (tag, layout) in &now.layouts {
+            if old.layouts.get(tag) != Some(layout) {
+                events.emit(Event::LayoutChanged {
+                    workspace: tag.clone(),
+                    layout: layout.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+// Titles change without a refresh, so they are passed on from their property events.
+pub fn event_hook(events: Events) -> impl EventHook<RustConn> {
+    move |event: &XEvent, state: &mut State<RustConn>, x: &RustConn| -> Result<bool> {
+        match event {
+            XEvent::PropertyNotify(PropertyEvent {
+                id,
+                atom,
+                is_root: false,
+            }) if matches!(atom.as_str(), "WM_NAME" | "_NET_WM_NAME")
+                && state.client_set.contains(id) =>
+            {
+                events.emit(Event::WindowTitleChanged {
+                    window: **id,
+                    title: x.window_title(*id).unwrap_or_default(),
+                });
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+}
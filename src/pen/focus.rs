@@ -0,0 +1,97 @@
+use crate::config::FocusModel;
+use penrose::core::bindings::{MouseButton, MouseEvent, MouseEventKind};
+use penrose::core::hooks::{EventHook, ManageHook, StateHook};
+use penrose::core::{Config as PConfig, State};
+use penrose::pure::geometry::Point;
+use penrose::x::{XConn, XConnExt, XEvent};
+use penrose::x11rb::RustConn;
+use penrose::{Result, Xid};
+use penrose_x11rb::protocol::xproto::{
+    Allow, ButtonIndex, ConnectionExt, EventMask, GrabMode, ModMask,
+};
+use penrose_x11rb::{CURRENT_TIME, NONE};
+use std::cell::Cell;
+use std::rc::Rc;
+
+// Penrose focuses the client under the pointer on every EnterNotify, which is what
+// FollowsMouse asks for. The other focus models are built on top of that with the hooks
+// below, penrose's own handling is switched off for Click through focus_follow_mouse.
+
+// Grabs the left button on new clients for Click, so clicks reach us before the client.
+fn manage_hook() -> impl ManageHook<RustConn> {
+    |id: Xid, _: &mut State<RustConn>, x: &RustConn| -> Result<()> {
+        // the pointer is frozen until the click is replayed to the client
+        x.connection().grab_button(
+            false,
+            *id,
+            EventMask::BUTTON_PRESS,
+            GrabMode::SYNC,
+            GrabMode::ASYNC,
+            NONE,
+            NONE,
+            ButtonIndex::M1,
+            ModMask::ANY,
+        )?;
+        Ok(())
+    }
+}
+
+// Focuses clicked clients and passes the click on to them.
+fn click_hook() -> impl EventHook<RustConn> {
+    |event: &XEvent, state: &mut State<RustConn>, x: &RustConn| -> Result<bool> {
+        let id: Xid = match event {
+            XEvent::MouseEvent(MouseEvent {
+                id,
+                state: mouse,
+                kind: MouseEventKind::Press,
+                ..
+            }) if mouse.button == MouseButton::Left && *id != x.root() => *id,
+            _ => return Ok(true),
+        };
+        if state.client_set.contains(&id) && state.client_set.current_client() != Some(&id) {
+            x.modify_and_refresh(state, |cs| cs.focus_client(&id))?;
+        }
+        x.connection()
+            .allow_events(Allow::REPLAY_POINTER, CURRENT_TIME)?;
+        Ok(false)
+    }
+}
+
+// Where the pointer was after the last refresh. Clients which are moved under the
+// pointer by a refresh cause EnterNotify events at exactly that position.
+type SharedPointer = Rc<Cell<Option<Point>>>;
+
+fn sloppy_refresh_hook(pointer: SharedPointer) -> impl StateHook<RustConn> {
+    move |_: &mut State<RustConn>, x: &RustConn| -> Result<()> {
+        pointer.set(x.cursor_position().ok());
+        Ok(())
+    }
+}
+
+// Skips penrose's focus handling for EnterNotify events the pointer did not cause.
+fn sloppy_event_hook(pointer: SharedPointer) -> impl EventHook<RustConn> {
+    move |event: &XEvent, _: &mut State<RustConn>, _: &RustConn| -> Result<bool> {
+        match event {
+            XEvent::Enter(p) if pointer.get() == Some(p.abs) => Ok(false),
+            _ => Ok(true),
+        }
+    }
+}
+
+// Adds the hooks of a focus model to the config.
+pub fn add_focus_hooks(model: FocusModel, conf: &mut PConfig<RustConn>) {
+    match model {
+        FocusModel::Click => {
+            conf.focus_follow_mouse = false;
+            conf.compose_or_set_manage_hook(manage_hook());
+            conf.compose_or_set_event_hook(click_hook());
+        }
+        FocusModel::FollowsMouse => conf.focus_follow_mouse = true,
+        FocusModel::Sloppy => {
+            let pointer: SharedPointer = Rc::new(Cell::new(None));
+            conf.focus_follow_mouse = true;
+            conf.compose_or_set_refresh_hook(sloppy_refresh_hook(pointer.clone()));
+            conf.compose_or_set_event_hook(sloppy_event_hook(pointer));
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use crate::hints::{ClientHints, SizeHints};
+use penrose::core::hooks::{EventHook, ManageHook};
+use penrose::core::layout::{Layout, LayoutTransformer};
+use penrose::core::State;
+use penrose::pure::geometry::Rect;
+use penrose::x::{event::PropertyEvent, ClientAttr, XConn, XConnExt, XEvent};
+use penrose::x11rb::RustConn;
+use penrose::{Color, Result, Xid};
+use penrose_x11rb::errors::{ConnectionError, ReplyError};
+use penrose_x11rb::properties::{WmHints, WmHintsState, WmSizeHints};
+use penrose_x11rb::protocol::xproto::{AtomEnum, ConnectionExt};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use tracing::{info, warn};
+
+// Penrose only exposes the min and max size of WM_NORMAL_HINTS, so the ICCCM hints are
+// read here and shared between the hooks and the layout transformer below. The input
+// model needs no handling here, penrose already sends WM_TAKE_FOCUS instead of setting
+// focus for clients which do not accept input.
+#[derive(Default)]
+pub struct HintsTracker {
+    hints: HashMap<Xid, ClientHints>,
+}
+
+pub type SharedHints = Rc<RefCell<HintsTracker>>;
+
+impl HintsTracker {
+    // re-reads the hints of a client and returns them together with the previous ones
+    fn update(&mut self, id: Xid, x: &RustConn) -> Option<(ClientHints, ClientHints)> {
+        match read_hints(id, x) {
+            Ok(hints) => {
+                let previous: ClientHints = self.hints.insert(id, hints).unwrap_or_default();
+                Some((hints, previous))
+            }
+            Err(error) => {
+                warn!("Could not read hints of client {}: {}", id, error);
+                None
+            }
+        }
+    }
+
+    pub fn get(&self, id: Xid) -> ClientHints {
+        self.hints.get(&id).copied().unwrap_or_default()
+    }
+}
+
+// Reads the hints like crate::hints::get_client_hints does, over the connection of penrose
+// and so with its version of x11rb. Properties which are missing or malformed count as
+// not set.
+fn read_hints(id: Xid, x: &RustConn) -> Result<ClientHints> {
+    let conn = x.connection();
+    let wm_protocols: Xid = x.intern_atom("WM_PROTOCOLS")?;
+    let wm_take_focus: Xid = x.intern_atom("WM_TAKE_FOCUS")?;
+    let size_cookie = WmSizeHints::get_normal_hints(conn, *id)?;
+    let hints_cookie = WmHints::get(conn, *id)?;
+    let protocols_cookie =
+        conn.get_property(false, *id, *wm_protocols, AtomEnum::ATOM, 0, u32::MAX)?;
+
+    let size: SizeHints = match ignore_malformed(size_cookie.reply())? {
+        Some(hints) => SizeHints::new(
+            hints.min_size,
+            hints.max_size,
+            hints.base_size,
+            hints.size_increment,
+            hints.aspect.map(|(min, max)| {
+                (
+                    (min.numerator, min.denominator),
+                    (max.numerator, max.denominator),
+                )
+            }),
+        ),
+        None => SizeHints::default(),
+    };
+    let wm_hints: WmHints = ignore_malformed(hints_cookie.reply())?.unwrap_or_default();
+    let take_focus: bool = protocols_cookie
+        .reply()?
+        .value32()
+        .map(|mut atoms| atoms.any(|atom| atom == *wm_take_focus))
+        .unwrap_or(false);
+
+    Ok(ClientHints::new(
+        size,
+        wm_hints.input,
+        take_focus,
+        wm_hints.urgent,
+        matches!(wm_hints.initial_state, Some(WmHintsState::Iconic)),
+    ))
+}
+
+// this version of x11rb fails to parse missing properties instead of returning None
+fn ignore_malformed<T>(
+    reply: std::result::Result<T, ReplyError>,
+) -> std::result::Result<Option<T>, ReplyError> {
+    match reply {
+        Ok(value) => Ok(Some(value)),
+        Err(ReplyError::ConnectionError(ConnectionError::ParseError(_))) => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+// Reads the hints of new clients and floats those which can not be resized.
+pub fn manage_hook(tracker: SharedHints) -> impl ManageHook<RustConn> {
+    move |id: Xid, state: &mut State<RustConn>, x: &RustConn| -> Result<()> {
+        // the client may already have been unmanaged again, e.g. as a dock
+        if !state.client_set.contains(&id) {
+            return Ok(());
+        }
+        let hints: ClientHints = match tracker.borrow_mut().update(id, x) {
+            Some((hints, _)) => hints,
+            None => return Ok(()),
+        };
+        if hints.size.is_fixed() {
+            let r: Rect = floating_position(id, hints, state, x)?;
+            state.client_set.float(id, r)?;
+        }
+        Ok(())
+    }
+}
+
+// Keeps the hints up to date and marks urgent clients with urgent_border.
+pub fn event_hook(tracker: SharedHints, urgent_border: u32) -> impl EventHook<RustConn> {
+    move |event: &XEvent, state: &mut State<RustConn>, x: &RustConn| -> Result<bool> {
+        match event {
+            XEvent::PropertyNotify(PropertyEvent {
+                id,
+                atom,
+                is_root: false,
+            }) if matches!(
+                atom.as_str(),
+                "WM_NORMAL_HINTS" | "WM_HINTS" | "WM_PROTOCOLS"
+            ) && state.client_set.contains(id) =>
+            {
+                let (hints, previous) = match tracker.borrow_mut().update(*id, x) {
+                    Some(update) => update,
+                    None => return Ok(true),
+                };
+                let focused: bool = state.client_set.current_client() == Some(id);
+                if hints.urgent != previous.urgent && !focused {
+                    info!("Client {} urgency changed to {}", id, hints.urgent);
+                    let color: Color = if hints.urgent {
+                        Color::new_from_hex(urgent_border)
+                    } else {
+                        state.config.normal_border
+                    };
+                    let border: ClientAttr = ClientAttr::BorderColor(color.argb_u32());
+                    x.set_client_attributes(*id, &[border])?;
+                }
+                if hints.size.is_fixed() && !previous.size.is_fixed() {
+                    let r: Rect = floating_position(*id, hints, state, x)?;
+                    x.modify_and_refresh(state, |cs| {
+                        let _ = cs.float(*id, r);
+                    })?;
+                }
+            }
+            XEvent::Destroy(id) => {
+                tracker.borrow_mut().hints.remove(id);
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+}
+
+// the current geometry of the client within its size hints, centered on the focused screen
+fn floating_position(
+    id: Xid,
+    hints: ClientHints,
+    state: &State<RustConn>,
+    x: &RustConn,
+) -> Result<Rect> {
+    let mut r: Rect = x.client_geometry(id)?;
+    (r.w, r.h) = hints.size.constrain(r.w, r.h);
+    let screen: Rect = state.client_set.current_screen().geometry();
+    Ok(r.centered_in(&screen).unwrap_or(r))
+}
+
+// Shrinks tiled clients to their resize increments, e.g. terminals to whole cells.
+// Positions include the border penrose draws, which is taken off before rounding.
+#[derive(Clone)]
+pub struct SizeIncrements {
+    layout: Box<dyn Layout>,
+    tracker: SharedHints,
+    border: u32,
+}
+
+impl SizeIncrements {
+    pub fn wrap(layout: Box<dyn Layout>, tracker: SharedHints, border: u32) -> Box<dyn Layout> {
+        Box::new(Self {
+            layout,
+            tracker,
+            border,
+        })
+    }
+}
+
+impl LayoutTransformer for SizeIncrements {
+    fn transformed_name(&self) -> String {
+        self.layout.name()
+    }
+
+    fn inner_mut(&mut self) -> &mut Box<dyn Layout> {
+        &mut self.layout
+    }
+
+    fn transform_positions(&mut self, _r: Rect, positions: Vec<(Xid, Rect)>) -> Vec<(Xid, Rect)> {
+        let tracker = self.tracker.borrow();
+        let border: u32 = 2 * self.border;
+        positions
+            .into_iter()
+            .map(|(id, mut r)| {
+                if r.w > border && r.h > border {
+                    let (w, h) = tracker
+                        .get(id)
+                        .size
+                        .apply_increments(r.w - border, r.h - border);
+                    (r.w, r.h) = (w + border, h + border);
+                }
+                (id, r)
+            })
+            .collect()
+    }
+}
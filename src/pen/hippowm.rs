@@ -1,11 +1,28 @@
+use crate::bar::Bar;
 use crate::config::Config;
+use crate::ipc::protocol::{Command, Reply};
+use crate::ipc::server::{self, Events};
+use crate::pen::bar;
+use crate::pen::docks::{self, ReserveStruts, SharedDocks};
+use crate::pen::events;
+use crate::pen::focus::add_focus_hooks;
+use crate::pen::hints::{self, SharedHints, SizeIncrements};
+use crate::pen::ipc;
+use crate::pen::restart::{self, SharedLayouts};
+use crate::pen::rules;
+use crate::pen::screens::{self, SharedOutputs};
+use crate::pen::shutdown;
+use crate::pen::transients::{self, SharedTransients};
+use crate::pen::tree::{self, SharedFloating};
+use crate::restart::SavedLayout;
+use crate::rules::Rules;
 use penrose::builtin::actions::floating::float_focused;
 use penrose::builtin::actions::key_handler;
 use penrose::builtin::layout::messages::ExpandMain;
 use penrose::core::bindings::KeyBindings;
 use penrose::{
     builtin::{
-        actions::{modify_with, send_layout_message},
+        actions::modify_with,
         layout::{
             messages::{IncMain, ShrinkMain},
             //build in layout transformers e.g. gaps around windows
@@ -16,7 +33,7 @@ use penrose::{
     },
     core::{
         bindings::{parse_keybindings_with_xmodmap, KeyEventHandler},
-        layout::LayoutStack as ls,
+        layout::{IntoMessage, LayoutStack as ls},
         Config as PConfig, State, WindowManager,
     },
     extensions::{actions::toggle_fullscreen, hooks::add_ewmh_hooks},
@@ -27,14 +44,19 @@ use penrose::{
 };
 use std::borrow::Cow;
 use std::collections::HashMap;
-use tracing::Level;
+use std::sync::mpsc;
+use tracing::{warn, Level};
 use tracing_subscriber::{util::SubscriberInitExt, FmtSubscriber};
 
+use crate::util::{force_kill_after, notify_on_signals};
 use penrose::core::hooks::StateHook;
+use penrose::pure::geometry::Rect;
 use penrose::util::spawn_with_args;
-use penrose::x11rb::Conn;
+use penrose::x::{WinType, XConnExt};
+use penrose::Xid;
+use std::time::Duration;
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 struct HippoWM {
     top_gaps: u32,
     outer_gaps: u32,
@@ -42,26 +64,67 @@ struct HippoWM {
     ratio: f32,
     ratio_step: f32,
     max_main: u32,
+    force_kill_timeout: u64,
+    honor_size_increments: bool,
+    hints: SharedHints,
+    docks: SharedDocks,
+    transients: SharedTransients,
+    outputs: SharedOutputs,
+    events: Events,
+    layouts: SharedLayouts,
+    floating: SharedFloating,
 }
 
+// Width of the border penrose draws around every client.
+const BORDER_WIDTH: u32 = 2;
+
+// Names of the layouts with a main area, which keep main area settings of their own.
+// Monocle shows one client at a time and ignores the messages changing them.
+const MAIN_LAYOUTS: [&str; 3] = ["Side", "Reflected<Side>", "Bottom"];
+
 impl HippoWM {
     //creates layouts and returns them as a Layout Stack
     fn get_layouts(&self) -> ls {
+        self.layouts_with(&[])
+    }
+
+    // creates the layouts with the main area settings of the same name, the layouts
+    // missing get the ones of the config
+    fn layouts_with(&self, settings: &[SavedLayout]) -> ls {
+        let main = |name: &str| -> (u32, f32) {
+            match settings.iter().find(|s| s.name == name) {
+                Some(s) => (s.max_main, s.ratio),
+                None => (self.max_main, self.ratio),
+            }
+        };
+        let [side, reflected, bottom] = MAIN_LAYOUTS;
+        let (side_main, side_ratio) = main(side);
+        let (reflected_main, reflected_ratio) = main(reflected);
+        let (bottom_main, bottom_ratio) = main(bottom);
         return stack!(
-            MainAndStack::side(self.max_main, self.ratio, self.ratio_step),
+            MainAndStack::side(side_main, side_ratio, self.ratio_step),
             ReflectHorizontal::wrap(MainAndStack::side(
-                self.max_main,
-                self.ratio,
+                reflected_main,
+                reflected_ratio,
                 self.ratio_step
             )),
-            MainAndStack::bottom(self.max_main, self.ratio, self.ratio_step),
+            MainAndStack::bottom(bottom_main, bottom_ratio, self.ratio_step),
             Monocle::boxed()
         )
         .map(|l| {
-            ReserveTop::wrap(
-                Gaps::wrap(l, self.outer_gaps, self.inner_gaps),
-                self.top_gaps,
-            )
+            // top_gaps is reserved on top of the space docks ask for
+            let l = ReserveStruts::wrap(
+                ReserveTop::wrap(
+                    Gaps::wrap(l, self.outer_gaps, self.inner_gaps),
+                    self.top_gaps,
+                ),
+                self.docks.clone(),
+            );
+            if self.honor_size_increments {
+                SizeIncrements::wrap(l, self.hints.clone(), BORDER_WIDTH)
+            } else {
+                l
+            }
         });
     }
 
@@ -96,6 +159,8 @@ impl HippoWM {
         self.outer_gaps = config.outer_gap;
         self.ratio = config.ratio;
         self.ratio_step = config.ratio_steps;
+        self.force_kill_timeout = config.force_kill_timeout;
+        self.honor_size_increments = config.honor_size_increments;
 
         // set keybinds to default ones
         let mut kb: HashMap<String, Box<dyn KeyEventHandler<RustConn>>> = Default::default();
@@ -146,7 +211,7 @@ impl HippoWM {
 
     fn action(&self, action: &str) -> Option<Box<dyn KeyEventHandler<RustConn>>> {
         match action.to_lowercase().as_str() {
-            "kill" => Some(modify_with(|a| a.kill_focused())),
+            "kill" => Some(self.kill_focused()),
             "focusnext" => Some(modify_with(|a| a.focus_down())), //focus element down the stack
             "focusprevious" => Some(modify_with(|a| a.focus_up())), //focus element up the stack
             "nextlayout" => Some(modify_with(|a| a.next_layout())),
@@ -156,13 +221,144 @@ impl HippoWM {
             "swapdown" => Some(modify_with(|a| a.swap_down())),
             "floatfocused" => Some(float_focused()),
             "toggletag" => Some(modify_with(|a| a.toggle_tag())),
-            "incmain" => Some(send_layout_message(|| IncMain(1))),
-            "decmain" => Some(send_layout_message(|| IncMain(-1))),
-            "expandmain" => Some(send_layout_message(|| ExpandMain)),
-            "shrmain" => Some(send_layout_message(|| ShrinkMain)),
+            "incmain" => Some(self.change_main(|| IncMain(1), |l, _| l.max_main += 1)),
+            "decmain" => Some(self.change_main(
+                || IncMain(-1),
+                |l, _| l.max_main = l.max_main.saturating_sub(1),
+            )),
+            "expandmain" => {
+                Some(self.change_main(|| ExpandMain, |l, step| l.ratio = (l.ratio + step).min(1.0)))
+            }
+            "shrmain" => {
+                Some(self.change_main(|| ShrinkMain, |l, step| l.ratio = (l.ratio - step).max(0.0)))
+            }
+            "restart" => Some(restart::restart(
+                self.events.clone(),
+                self.layouts.clone(),
+                self.floating.clone(),
+            )),
             _ => None,
         }
     }
+
+    // runs a command of the socket, the same way the key bound to it would
+    fn command(
+        &mut self,
+        command: &Command,
+        state: &mut State<RustConn>,
+        x: &RustConn,
+    ) -> Result<Reply> {
+        match command {
+            Command::Action { name } => match self.action(name) {
+                Some(mut action) => action.call(state, x)?,
+                None => return Ok(Reply::error(format!("Unknown action {}", name))),
+            },
+            Command::FocusWorkspace { workspace } | Command::MoveWindow { workspace }
+                if !state.client_set.contains_tag(workspace) =>
+            {
+                return Ok(Reply::error(format!("Unknown workspace {}", workspace)));
+            }
+            Command::FocusWorkspace { workspace } => {
+                x.modify_and_refresh(state, |cs| cs.focus_tag(workspace))?
+            }
+            Command::MoveWindow { workspace } => {
+                x.modify_and_refresh(state, |cs| cs.move_focused_to_tag(workspace))?
+            }
+            Command::Spawn { program } => {
+                if let Err(error) = util::spawn(program.as_str()) {
+                    return Ok(Reply::error(format!(
+                        "Could not spawn {}: {}",
+                        program, error
+                    )));
+                }
+            }
+            // penrose layouts only step their ratio, so the layouts of the focused
+            // workspace are replaced by ones with the new ratio
+            Command::SetRatio { ratio } => {
+                let workspace = state.client_set.current_workspace_mut();
+                let mut layouts = self.layouts.borrow_mut();
+                let settings: &mut Vec<SavedLayout> =
+                    layouts.entry(workspace.tag().to_string()).or_default();
+                for name in MAIN_LAYOUTS {
+                    self.main_settings(settings, name).ratio = ratio.clamp(0.0, 1.0);
+                }
+                let name: String = workspace.layout_name();
+                workspace.set_available_layouts(self.layouts_with(settings));
+                workspace.set_layout_by_name(&name);
+                drop(layouts);
+                x.refresh(state)?;
+            }
+            // answered by the socket, it never gets here
+            Command::Subscribe { .. } => {}
+            Command::GetTree | Command::GetWorkspaces | Command::GetOutputs => {
+                return Ok(tree::tree(state, x, &self.floating.borrow())?.reply(command));
+            }
+        }
+        Ok(Reply::ok())
+    }
+
+    // the main area settings of a layout of a workspace, which start out as the ones of
+    // the config
+    fn main_settings<'a>(
+        &self,
+        settings: &'a mut Vec<SavedLayout>,
+        name: &str,
+    ) -> &'a mut SavedLayout {
+        let index: usize = match settings.iter().position(|s| s.name == name) {
+            Some(index) => index,
+            None => {
+                settings.push(SavedLayout {
+                    name: name.to_string(),
+                    max_main: self.max_main,
+                    ratio: self.ratio,
+                });
+                settings.len() - 1
+            }
+        };
+        &mut settings[index]
+    }
+
+    // sends a message to the active layout of the focused workspace like
+    // send_layout_message does, and records the same change in its main area settings
+    fn change_main<M: IntoMessage>(
+        &self,
+        message: fn() -> M,
+        change: fn(&mut SavedLayout, f32),
+    ) -> Box<dyn KeyEventHandler<RustConn>> {
+        let hippowm: HippoWM = self.clone();
+        key_handler(move |state, x: &RustConn| {
+            let workspace = state.client_set.current_workspace();
+            if MAIN_LAYOUTS.contains(&workspace.layout_name().as_str()) {
+                let mut layouts = hippowm.layouts.borrow_mut();
+                let settings: &mut Vec<SavedLayout> =
+                    layouts.entry(workspace.tag().to_string()).or_default();
+                change(
+                    hippowm.main_settings(settings, &workspace.layout_name()),
+                    hippowm.ratio_step,
+                );
+            }
+            x.modify_and_refresh(state, |cs| {
+                cs.current_workspace_mut().handle_message(message())
+            })
+        })
+    }
+
+    // kills the focused client like kill_focused does, penrose already sends WM_DELETE_WINDOW
+    // if the client supports it. Hung clients are killed after force_kill_timeout.
+    fn kill_focused(&self) -> Box<dyn KeyEventHandler<RustConn>> {
+        let timeout: u64 = self.force_kill_timeout;
+        key_handler(move |state, x: &RustConn| {
+            let focused: Option<Xid> = state.client_set.current_client().copied();
+            let pid: Option<u32> = focused.and_then(|id| x.window_pid(id));
+            x.modify_and_refresh(state, |cs| cs.kill_focused())?;
+            if let Some(id) = focused {
+                if timeout > 0 {
+                    force_kill_after(None, *id, pid, Duration::from_millis(timeout));
+                }
+            }
+            Ok(())
+        })
+    }
 }
 
 //creates and runs the actual WM
@@ -191,22 +387,80 @@ pub fn run(config: Config) -> Result<()> {
 
     let mut hippowm: HippoWM = HippoWM::default();
     let kb: HashMap<String, Box<dyn KeyEventHandler<RustConn>>> = hippowm.configure(config.clone());
+    let status_bar: Option<Bar> = if config.bar {
+        Bar::spawn(None, &config)
+            .map_err(|error| warn!("Could not create the bar: {}", error))
+            .ok()
+    } else {
+        None
+    };
 
-    let conf = add_ewmh_hooks(PConfig {
+    let mut conf: PConfig<RustConn> = PConfig {
         default_layouts: hippowm.get_layouts(),
         normal_border: Color::new_from_hex(config.border),
         focused_border: Color::new_from_hex(config.focused_border),
+        border_width: BORDER_WIDTH,
         startup_hook,
         tags: config.workspaces,
         ..PConfig::default()
-    });
+    };
+    // composed first, so the bar is updated after the hints of a client
+    if let Some(status_bar) = status_bar {
+        conf.compose_or_set_refresh_hook(bar::refresh_hook(
+            status_bar.clone(),
+            hippowm.hints.clone(),
+        ));
+        conf.compose_or_set_event_hook(bar::event_hook(status_bar, hippowm.hints.clone()));
+    }
+    // rules are applied last, so they can override the floating of the other hooks
+    let window_rules: Rules = Rules::new(&config.rules);
+    if !window_rules.is_empty() {
+        conf.compose_or_set_manage_hook(rules::manage_hook(window_rules));
+    }
+    conf.compose_or_set_manage_hook(transients::manage_hook(hippowm.transients.clone()));
+    conf.compose_or_set_event_hook(transients::event_hook(hippowm.transients.clone()));
+    conf.compose_or_set_refresh_hook(transients::refresh_hook(hippowm.transients.clone()));
+    conf.compose_or_set_manage_hook(hints::manage_hook(hippowm.hints.clone()));
+    conf.compose_or_set_event_hook(hints::event_hook(
+        hippowm.hints.clone(),
+        config.urgent_border,
+    ));
+    conf.compose_or_set_event_hook(screens::event_hook(hippowm.outputs.clone()));
+    conf.compose_or_set_refresh_hook(screens::refresh_hook(hippowm.outputs.clone()));
+    // composed hooks run before the ones already set, docks are unmanaged first
+    conf.compose_or_set_manage_hook(docks::manage_hook(hippowm.docks.clone()));
+    conf.compose_or_set_event_hook(docks::event_hook(hippowm.docks.clone()));
+    add_focus_hooks(config.focus, &mut conf);
+    conf.compose_or_set_event_hook(shutdown::event_hook());
+    let builder: HippoWM = hippowm.clone();
+    conf.compose_or_set_refresh_hook(restart::refresh_hook(
+        hippowm.layouts.clone(),
+        move |settings| builder.layouts_with(settings),
+    ));
+    conf.compose_or_set_refresh_hook(events::refresh_hook(hippowm.events.clone()));
+    conf.compose_or_set_refresh_hook(tree::refresh_hook(hippowm.floating.clone()));
+    conf.compose_or_set_event_hook(events::event_hook(hippowm.events.clone()));
+    // the socket is only opened once we manage the screen, until then nothing arrives
+    let (sender, requests) = mpsc::channel();
+    let mut handler: HippoWM = hippowm.clone();
+    conf.compose_or_set_event_hook(ipc::event_hook(requests, move |command, state, x| {
+        handler.command(command, state, x)
+    }));
+    let conf = add_ewmh_hooks(conf);
 
     let keys: KeyBindings<RustConn> = parse_keybindings_with_xmodmap(hippowm.ws_binds(kb))?;
     let rustc = RustConn::new()?;
+    // an unmapped window of our own, which receives IPC_MESSAGE
+    let ipc_window: Xid = rustc.create_window(WinType::CheckWin, Rect::new(0, 0, 1, 1), false)?;
+    if let Err(error) = notify_on_signals(None) {
+        warn!("Could not handle shutdown signals: {}", error);
+    }
     let wm: WindowManager<RustConn> = WindowManager::new(conf, keys, HashMap::new(), rustc)?;
+    if let Err(error) = server::listen(None, *ipc_window, sender, hippowm.events.clone()) {
+        warn!("Could not listen for commands: {}", error);
+    }
 
-    wm.run().unwrap();
-    Ok(())
+    wm.run()
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
@@ -238,3 +492,20 @@ where
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn main_layouts_are_named_like_the_penrose_layouts() {
+        let names: Vec<String> = HippoWM::default()
+            .get_layouts()
+            .iter()
+            .map(|l| l.name())
+            .collect();
+        assert_eq!(names, ["Side", "Reflected<Side>", "Bottom", "Mono"]);
+        assert_eq!(names[..3], MAIN_LAYOUTS);
+        assert!(!MAIN_LAYOUTS.contains(&Monocle::boxed().name().as_str()));
+    }
+}
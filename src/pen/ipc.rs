@@ -0,0 +1,31 @@
+use crate::ipc::protocol::{Command, Reply};
+use crate::ipc::server::{Request, IPC_MESSAGE};
+use penrose::core::hooks::EventHook;
+use penrose::core::State;
+use penrose::x::XEvent;
+use penrose::x11rb::RustConn;
+use penrose::Result;
+use std::sync::mpsc::Receiver;
+use tracing::info;
+
+// Runs the commands which arrived on the socket whenever IPC_MESSAGE wakes us up. What a
+// command does is up to run, which knows the actions of HippoWM.
+pub fn event_hook<F>(requests: Receiver<Request>, mut run: F) -> impl EventHook<RustConn>
+where
+    F: FnMut(&Command, &mut State<RustConn>, &RustConn) -> Result<Reply> + 'static,
+{
+    move |event: &XEvent, state: &mut State<RustConn>, x: &RustConn| -> Result<bool> {
+        match event {
+            XEvent::ClientMessage(m) if m.dtype == IPC_MESSAGE => {}
+            _ => return Ok(true),
+        }
+        for request in requests.try_iter() {
+            info!("Received command {:?}", request.command);
+            match run(&request.command, state, x) {
+                Ok(reply) => request.reply(reply),
+                Err(error) => request.reply(Reply::error(error.to_string())),
+            }
+        }
+        Ok(false)
+    }
+}
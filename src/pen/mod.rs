@@ -1 +1,13 @@
+pub mod bar;
+pub mod docks;
+pub mod events;
+pub mod focus;
+pub mod hints;
+pub mod ipc;
 pub mod hippowm;
+pub mod restart;
+pub mod rules;
+pub mod screens;
+pub mod shutdown;
+pub mod transients;
+pub mod tree;
@@ -0,0 +1,156 @@
+use crate::ipc::protocol::Event;
+use crate::ipc::server::Events;
+use crate::layout::Rect;
+use crate::pen::tree::SharedFloating;
+use crate::restart::{self, SavedLayout, SavedState, SavedWorkspace};
+use penrose::builtin::actions::key_handler;
+use penrose::core::bindings::KeyEventHandler;
+use penrose::core::hooks::StateHook;
+use penrose::core::layout::LayoutStack;
+use penrose::core::State;
+use penrose::pure::geometry::Rect as PRect;
+use penrose::x::XConn;
+use penrose::x::XConnExt;
+use penrose::x11rb::RustConn;
+use penrose::{Error, Result, Xid};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use tracing::{error, info};
+
+// Penrose layouts keep their main area settings to themselves, so the ones changed on every
+// workspace are tracked here by tag, the layouts missing keep the settings of the config.
+pub type SharedLayouts = Rc<RefCell<HashMap<String, Vec<SavedLayout>>>>;
+
+// Saves the state and execs HippoWM again. Penrose does not reparent, so the clients stay
+// where they are, and those of hidden workspaces are iconic, which penrose manages again.
+// Subscribers are told the configuration is reloaded, their streams end with the exec.
+pub fn restart(
+    events: Events,
+    layouts: SharedLayouts,
+    floating: SharedFloating,
+) -> Box<dyn KeyEventHandler<RustConn>> {
+    key_handler(move |state, x: &RustConn| {
+        let saved: SavedState = saved_state(state, x, &layouts.borrow(), &floating.borrow())?;
+        let path = restart::save(&saved).map_err(Error::Custom)?;
+        x.flush();
+        events.emit(Event::ConfigReloaded);
+        error!("{}", restart::exec(&path));
+        Ok(())
+    })
+}
+
+// Everything a restart needs to put the clients back where they are.
+fn saved_state(
+    state: &State<RustConn>,
+    x: &RustConn,
+    layouts: &HashMap<String, Vec<SavedLayout>>,
+    floating_clients: &HashSet<Xid>,
+) -> Result<SavedState> {
+    let mut floating: HashMap<u32, Rect> = HashMap::new();
+    for id in floating_clients {
+        let r: PRect = x.client_geometry(*id)?;
+        floating.insert(**id, Rect::new(r.x as i32, r.y as i32, r.w, r.h));
+    }
+
+    let cs = &state.client_set;
+    let mut screens: Vec<(usize, String)> = cs
+        .screens()
+        .map(|s| (s.index(), s.workspace.tag().to_string()))
+        .collect();
+    screens.sort();
+    Ok(SavedState {
+        current: cs.current_tag().to_string(),
+        screens: screens.into_iter().map(|(_, tag)| tag).collect(),
+        workspaces: cs
+            .workspaces()
+            .map(|ws| SavedWorkspace {
+                name: ws.tag().to_string(),
+                clients: ws.clients().map(|id| **id).collect(),
+                focused: ws.focus().map(|id| **id),
+                layout: ws.layout_name(),
+                layouts: layouts.get(ws.tag()).cloned().unwrap_or_default(),
+            })
+            .collect(),
+        floating,
+    })
+}
+
+// Restores the state saved before a restart on the first refresh, which penrose runs once
+// it managed the existing clients. The layouts of workspaces with main area settings of
+// their own are replaced by the ones build creates with them.
+pub fn refresh_hook<F>(layouts: SharedLayouts, build: F) -> impl StateHook<RustConn>
+where
+    F: Fn(&[SavedLayout]) -> LayoutStack + 'static,
+{
+    let mut saved: Option<SavedState> = restart::take();
+    move |state: &mut State<RustConn>, x: &RustConn| -> Result<()> {
+        match saved.take() {
+            Some(saved) => restore(saved, &layouts, &build, state, x),
+            None => Ok(()),
+        }
+    }
+}
+
+fn restore(
+    saved: SavedState,
+    layouts: &SharedLayouts,
+    build: &dyn Fn(&[SavedLayout]) -> LayoutStack,
+    state: &mut State<RustConn>,
+    x: &RustConn,
+) -> Result<()> {
+    let cs = &mut state.client_set;
+    for ws in &saved.workspaces {
+        if !cs.contains_tag(&ws.name) {
+            continue;
+        }
+        // moved clients become the focus of their workspace, pushing the others down,
+        // so the last one is moved first
+        for id in ws.clients.iter().rev().map(|id| Xid::from(*id)) {
+            if cs.contains(&id) {
+                cs.move_client_to_tag(&id, &ws.name);
+            }
+        }
+        if let Some(workspace) = cs.workspace_mut(&ws.name) {
+            if !ws.layouts.is_empty() {
+                workspace.set_available_layouts(build(&ws.layouts));
+                layouts
+                    .borrow_mut()
+                    .insert(ws.name.clone(), ws.layouts.clone());
+            }
+            workspace.set_layout_by_name(&ws.layout);
+        }
+    }
+    // penrose can only float clients which are visible
+    for (id, r) in &saved.floating {
+        let id: Xid = Xid::from(*id);
+        if let Some(tag) = cs.tag_for_client(&id).map(|tag| tag.to_string()) {
+            cs.focus_tag(&tag);
+            let r: PRect = PRect::new(r.x.max(0) as u32, r.y.max(0) as u32, r.w, r.h);
+            cs.float(id, r)?;
+        }
+    }
+    for id in saved.workspaces.iter().filter_map(|ws| ws.focused) {
+        let id: Xid = Xid::from(id);
+        if cs.contains(&id) {
+            cs.focus_client(&id);
+        }
+    }
+    let n: usize = cs.screens().count();
+    for (i, tag) in saved.screens.iter().enumerate().take(n) {
+        if cs.contains_tag(tag) {
+            cs.focus_screen(i);
+            cs.pull_tag_to_screen(tag);
+        }
+    }
+    let screen: Option<usize> = cs
+        .screens()
+        .find(|s| s.workspace.tag() == saved.current)
+        .map(|s| s.index());
+    match screen {
+        Some(index) => cs.focus_screen(index),
+        None => cs.focus_tag(&saved.current),
+    }
+    info!("Restored the state saved before the restart");
+    x.refresh(state)
+}
@@ -0,0 +1,104 @@
+use crate::rules::{Effects, Rules, WindowProps};
+use penrose::core::hooks::ManageHook;
+use penrose::core::State;
+use penrose::pure::geometry::Rect;
+use penrose::x::{ClientConfig, Prop, XConn, XConnExt};
+use penrose::x11rb::RustConn;
+use penrose::{Result, Xid};
+use tracing::{info, warn};
+
+// Applies the window rules of the config to new clients.
+pub fn manage_hook(rules: Rules) -> impl ManageHook<RustConn> {
+    move |id: Xid, state: &mut State<RustConn>, x: &RustConn| -> Result<()> {
+        if !state.client_set.contains(&id) {
+            return Ok(());
+        }
+        let effects: Effects = rules.effects(&window_props(id, x));
+        if effects == Effects::default() {
+            return Ok(());
+        }
+        info!("Applying rules to client {}: {:?}", id, effects);
+
+        // Penrose can only float clients which are visible, so this happens while the
+        // client is still on the current workspace. Floating positions are relative to
+        // the screen and move along with the workspace.
+        let screen: Rect = state.client_set.current_screen().geometry();
+        if effects.fullscreen == Some(true) {
+            state.client_set.float(id, screen)?;
+            x.set_prop(
+                id,
+                "_NET_WM_STATE",
+                Prop::Atom(vec!["_NET_WM_STATE_FULLSCREEN".into()]),
+            )?;
+        } else if effects.is_floating(false) {
+            let r: Rect = match effects.geometry {
+                Some(g) => Rect::new(
+                    (screen.x as i32 + g.x).max(0) as u32,
+                    (screen.y as i32 + g.y).max(0) as u32,
+                    g.w.max(1),
+                    g.h.max(1),
+                ),
+                None => {
+                    let r: Rect = x.client_geometry(id)?;
+                    r.centered_in(&screen).unwrap_or(r)
+                }
+            };
+            state.client_set.float(id, r)?;
+        } else if effects.floating == Some(false) {
+            // e.g. dialogs or fixed-size clients, which are floated by default
+            state.client_set.sink(&id);
+        }
+
+        let tag: Option<String> = match (&effects.workspace, effects.monitor) {
+            (Some(tag), _) => Some(tag.clone()),
+            (None, Some(monitor)) => state
+                .client_set
+                .screens()
+                .find(|s| s.index() == monitor)
+                .map(|s| s.workspace.tag().to_string()),
+            (None, None) => None,
+        };
+        if let Some(tag) = tag {
+            if state.client_set.contains_tag(&tag) {
+                state.client_set.move_client_to_tag(&id, &tag);
+            } else {
+                warn!("Rule for client {} names unknown workspace {}", id, tag);
+            }
+        }
+
+        // the layout still leaves room for the border, only floating clients use it
+        if effects.no_border == Some(true) {
+            x.set_client_config(id, &[ClientConfig::BorderPx(0)])?;
+        }
+        // new clients take the focus, the previously focused client is the next one
+        if effects.focus == Some(false) && state.client_set.current_client() == Some(&id) {
+            state.client_set.focus_down();
+        }
+        Ok(())
+    }
+}
+
+// Reads the properties rules are matched against, unset ones are left empty.
+fn window_props(id: Xid, x: &RustConn) -> WindowProps {
+    let strings = |prop: &str| -> Vec<String> {
+        match x.get_prop(id, prop) {
+            Ok(Some(Prop::UTF8String(strings))) => strings,
+            _ => vec![],
+        }
+    };
+    let mut class = strings("WM_CLASS").into_iter();
+    let types: Vec<String> = match x.get_prop(id, "_NET_WM_WINDOW_TYPE") {
+        Ok(Some(Prop::Atom(types))) => types,
+        _ => vec![],
+    };
+    WindowProps {
+        instance: class.next().unwrap_or_default(),
+        class: class.next().unwrap_or_default(),
+        title: x.window_title(id).unwrap_or_default(),
+        role: strings("WM_WINDOW_ROLE")
+            .into_iter()
+            .next()
+            .unwrap_or_default(),
+        types,
+    }
+}
@@ -0,0 +1,170 @@
+use penrose::core::hooks::{EventHook, StateHook};
+use penrose::core::State;
+use penrose::pure::geometry::Rect;
+use penrose::x::event::{ClientEventMask, ClientMessage, ClientMessageData};
+use penrose::x::{XConn, XConnExt, XEvent};
+use penrose::x11rb::RustConn;
+use penrose::Result;
+use penrose_x11rb::protocol::randr::ConnectionExt;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use tracing::info;
+
+// Sent to ourselves when the screens changed. It arrives after penrose handled the RandR
+// events which were already queued, so its screens are up to date by then.
+const SCREENS_CHANGED: &str = "_HIPPOWM_SCREENS_CHANGED";
+
+// Penrose re-detects its screens on RandR events, but it fills them in the order of the
+// CRTCs, leaves workspaces wherever they end up and does not refresh. So the workspace
+// last shown on every output is kept here by output name, to put it back on the same
+// output once that is connected again, e.g. when a laptop is docked.
+#[derive(Default)]
+pub struct OutputMemory {
+    // name of the output of every screen, by screen index
+    outputs: Vec<String>,
+    // tag last shown on every output which was connected at some point
+    tags: HashMap<String, String>,
+    // a SCREENS_CHANGED message is on its way
+    pending: bool,
+}
+
+pub type SharedOutputs = Rc<RefCell<OutputMemory>>;
+
+// The active outputs with the area of their CRTC, in the way penrose reads the CRTCs.
+fn query_outputs(x: &RustConn) -> Result<Vec<(String, Rect)>> {
+    let conn = x.connection();
+    let resources = conn
+        .randr_get_screen_resources_current(*x.root())?
+        .reply()?;
+    let cookies = resources
+        .crtcs
+        .iter()
+        .map(|crtc| conn.randr_get_crtc_info(*crtc, resources.config_timestamp))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let mut outputs: Vec<(String, Rect)> = vec![];
+    for cookie in cookies {
+        let crtc = cookie.reply()?;
+        let output: u32 = match crtc.outputs.first() {
+            Some(output) if crtc.width > 0 => *output,
+            _ => continue,
+        };
+        let info = conn
+            .randr_get_output_info(output, resources.config_timestamp)?
+            .reply()?;
+        // the same casts penrose uses, so the areas compare equal to its screens
+        let r: Rect = Rect::new(
+            crtc.x as u32,
+            crtc.y as u32,
+            crtc.width as u32,
+            crtc.height as u32,
+        );
+        outputs.push((String::from_utf8_lossy(&info.name).into_owned(), r));
+    }
+    Ok(outputs)
+}
+
+// The name of the output of every screen by screen index, empty if there is none.
+pub fn screen_outputs(state: &State<RustConn>, x: &RustConn) -> Result<Vec<String>> {
+    let outputs: Vec<(String, Rect)> = query_outputs(x)?;
+    let mut names: Vec<String> = vec![String::new(); state.client_set.screens().count()];
+    for s in state.client_set.screens() {
+        if let Some((name, _)) = outputs.iter().find(|(_, r)| *r == s.geometry()) {
+            names[s.index()] = name.clone();
+        }
+    }
+    Ok(names)
+}
+
+// Puts workspaces back on the output they were last shown on. Outputs which were connected
+// before keep what they showed, new ones get their workspace back unless one of the others
+// shows it. The caller refreshes afterwards, once it let go of the memory, which the
+// refresh hook borrows as well.
+fn restore(memory: &mut OutputMemory, state: &mut State<RustConn>, x: &RustConn) -> Result<()> {
+    memory.pending = false;
+    let names: Vec<String> = screen_outputs(state, x)?;
+    info!("Screens changed, outputs are now {:?}", names);
+
+    let focused: String = state.client_set.current_tag().to_string();
+    let mut order: Vec<usize> = (0..names.len()).collect();
+    order.sort_by_key(|i| !memory.outputs.contains(&names[*i]));
+    let mut claimed: HashSet<String> = HashSet::new();
+    for i in order {
+        let tag: String = match memory.tags.get(&names[i]) {
+            Some(tag) if state.client_set.contains_tag(tag) && !claimed.contains(tag) => {
+                tag.clone()
+            }
+            _ => continue,
+        };
+        state.client_set.focus_screen(i);
+        state.client_set.pull_tag_to_screen(&tag);
+        claimed.insert(tag);
+    }
+    let screen: usize = state
+        .client_set
+        .screens()
+        .find(|s| s.workspace.tag() == focused)
+        .map(|s| s.index())
+        .unwrap_or(0);
+    state.client_set.focus_screen(screen);
+    memory.outputs = names;
+    Ok(())
+}
+
+// Schedules restoring the workspaces whenever penrose re-detects its screens.
+pub fn event_hook(outputs: SharedOutputs) -> impl EventHook<RustConn> {
+    move |event: &XEvent, state: &mut State<RustConn>, x: &RustConn| -> Result<bool> {
+        match event {
+            XEvent::RandrNotify => {}
+            XEvent::ConfigureNotify(e) if e.is_root => {}
+            XEvent::ClientMessage(m) if m.dtype == SCREENS_CHANGED => {
+                restore(&mut outputs.borrow_mut(), state, x)?;
+                // hides the clients of workspaces which are no longer on any screen
+                x.refresh(state)?;
+                return Ok(false);
+            }
+            _ => return Ok(true),
+        }
+        let memory: &mut OutputMemory = &mut outputs.borrow_mut();
+        if !memory.pending {
+            memory.pending = true;
+            x.send_client_message(ClientMessage::new(
+                x.root(),
+                ClientEventMask::SubstructureNotify,
+                SCREENS_CHANGED,
+                ClientMessageData::U32([0; 5]),
+            ))?;
+        }
+        Ok(true)
+    }
+}
+
+// Remembers which workspace every output shows.
+pub fn refresh_hook(outputs: SharedOutputs) -> impl StateHook<RustConn> {
+    move |state: &mut State<RustConn>, x: &RustConn| -> Result<()> {
+        // refreshes from within the event hook find the memory borrowed
+        let mut memory = match outputs.try_borrow_mut() {
+            Ok(memory) => memory,
+            Err(_) => return Ok(()),
+        };
+        let memory: &mut OutputMemory = &mut memory;
+        // the screens penrose just changed are only known once they were restored
+        if memory.pending {
+            return Ok(());
+        }
+        if memory.outputs.len() != state.client_set.screens().count() {
+            memory.outputs = screen_outputs(state, x)?;
+        }
+        for s in state.client_set.screens() {
+            match memory.outputs.get(s.index()) {
+                Some(name) if !name.is_empty() => {
+                    memory
+                        .tags
+                        .insert(name.clone(), s.workspace.tag().to_string());
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
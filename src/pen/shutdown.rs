@@ -0,0 +1,46 @@
+use crate::util::SHUTDOWN_MESSAGE;
+use penrose::builtin::actions::exit;
+use penrose::core::hooks::EventHook;
+use penrose::core::State;
+use penrose::x::property::WmState;
+use penrose::x::{Atom, ClientConfig, XConn, XEvent};
+use penrose::x11rb::RustConn;
+use penrose::{Result, Xid};
+use tracing::info;
+
+// Root properties set by the ewmh hooks, which would otherwise keep announcing us.
+const EWMH_PROPERTIES: [Atom; 7] = [
+    Atom::NetSupported,
+    Atom::NetClientList,
+    Atom::NetClientListStacking,
+    Atom::NetActiveWindow,
+    Atom::NetNumberOfDesktops,
+    Atom::NetDesktopNames,
+    Atom::NetCurrentDesktop,
+];
+
+// On SHUTDOWN_MESSAGE the clients are restored and penrose is told to leave its event loop,
+// main exits once it did. Penrose does not reparent, but it unmaps the clients of hidden
+// workspaces, which would be lost without a window manager.
+pub fn event_hook() -> impl EventHook<RustConn> {
+    move |event: &XEvent, state: &mut State<RustConn>, x: &RustConn| -> Result<bool> {
+        match event {
+            XEvent::ClientMessage(m) if m.dtype == SHUTDOWN_MESSAGE => {}
+            _ => return Ok(true),
+        }
+        let clients: Vec<Xid> = state.client_set.clients().copied().collect();
+        info!("Restoring {} clients", clients.len());
+        for id in clients {
+            // clients may be gone already, none of this is worth stopping for
+            let _ = x.set_client_config(id, &[ClientConfig::BorderPx(0)]);
+            let _ = x.set_wm_state(id, WmState::Normal);
+            let _ = x.map(id);
+        }
+        for property in EWMH_PROPERTIES {
+            let _ = x.delete_prop(x.root(), property.as_ref());
+        }
+        x.flush();
+        exit().call(state, x)?;
+        Ok(false)
+    }
+}
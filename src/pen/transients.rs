@@ -0,0 +1,86 @@
+use penrose::core::hooks::{EventHook, ManageHook, StateHook};
+use penrose::core::State;
+use penrose::pure::geometry::Rect;
+use penrose::x::{ClientConfig, Prop, XConn, XConnExt, XEvent};
+use penrose::x11rb::RustConn;
+use penrose::{Result, Xid};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// Penrose already floats transient windows and dialogs and puts transients on the
+// workspace of their parent. What it does not do is keep them with their parent
+// afterwards, so the parent of every managed transient is kept here.
+pub type SharedTransients = Rc<RefCell<HashMap<Xid, Xid>>>;
+
+fn transient_for(id: Xid, x: &RustConn) -> Option<Xid> {
+    match x.get_prop(id, "WM_TRANSIENT_FOR") {
+        Ok(Some(Prop::Window(ids))) => ids.first().copied(),
+        _ => None,
+    }
+}
+
+// Remembers the parent of new transients and centres them over it.
+pub fn manage_hook(transients: SharedTransients) -> impl ManageHook<RustConn> {
+    move |id: Xid, state: &mut State<RustConn>, x: &RustConn| -> Result<()> {
+        let parent: Xid = match transient_for(id, x) {
+            Some(parent) if parent != id && state.client_set.contains(&parent) => parent,
+            _ => return Ok(()),
+        };
+        transients.borrow_mut().insert(id, parent);
+
+        // floating only works for visible clients, penrose centred it on the screen otherwise
+        if state.client_set.screen_for_client(&id).is_none()
+            || state.client_set.screen_for_client(&parent).is_none()
+        {
+            return Ok(());
+        }
+        let r: Rect = x.client_geometry(id)?;
+        let over: Rect = x.client_geometry(parent)?;
+        if let Some(centered) = r.centered_in(&over) {
+            state.client_set.float(id, centered)?;
+        }
+        Ok(())
+    }
+}
+
+// Forgets transients which are gone, those of a parent which is gone are kept as they are.
+pub fn event_hook(transients: SharedTransients) -> impl EventHook<RustConn> {
+    move |event: &XEvent, _: &mut State<RustConn>, _: &RustConn| -> Result<bool> {
+        if let XEvent::Destroy(id) = event {
+            transients
+                .borrow_mut()
+                .retain(|child, parent| child != id && parent != id);
+        }
+        Ok(true)
+    }
+}
+
+// Moves transients to the workspace their parent was moved to and stacks the visible
+// ones above everything else, which keeps them above their parent.
+pub fn refresh_hook(transients: SharedTransients) -> impl StateHook<RustConn> {
+    move |state: &mut State<RustConn>, x: &RustConn| -> Result<()> {
+        let mut moved: bool = false;
+        for (child, parent) in transients.borrow().iter() {
+            let tag: Option<String> = state.client_set.tag_for_client(parent).map(String::from);
+            match tag {
+                Some(tag) if state.client_set.tag_for_client(child) != Some(tag.as_str()) => {
+                    state.client_set.move_client_to_tag(child, &tag);
+                    moved = true;
+                }
+                _ => {}
+            }
+        }
+        if moved {
+            // this hook is not run again by the refresh it triggers itself
+            x.refresh(state)?;
+        }
+
+        for child in transients.borrow().keys() {
+            if state.client_set.screen_for_client(child).is_some() {
+                x.set_client_config(*child, &[ClientConfig::StackTop])?;
+            }
+        }
+        Ok(())
+    }
+}
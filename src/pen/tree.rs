@@ -0,0 +1,107 @@
+use crate::ipc::protocol::{Client, Geometry, Output, Tree, Workspace};
+use crate::pen::screens::screen_outputs;
+use penrose::core::hooks::StateHook;
+use penrose::core::{ClientSet, State};
+use penrose::pure::geometry::Rect;
+use penrose::x::property::Prop;
+use penrose::x::{XConn, XConnExt};
+use penrose::x11rb::RustConn;
+use penrose::{Result, Xid};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+// The floating clients as of the last refresh.
+pub type SharedFloating = Rc<RefCell<HashSet<Xid>>>;
+
+// Penrose 0.3.4 keeps which clients float to itself, sinking a client is the only way to
+// find out whether it floated. That needs a copy of the client set, so it is only done once
+// per refresh, which follows every change of the state, and only for the workspaces which
+// have floating clients at all.
+pub fn refresh_hook(floating: SharedFloating) -> impl StateHook<RustConn> {
+    move |state: &mut State<RustConn>, _: &RustConn| -> Result<()> {
+        *floating.borrow_mut() = find_floating(&state.client_set);
+        Ok(())
+    }
+}
+
+fn find_floating(client_set: &ClientSet) -> HashSet<Xid> {
+    let clients: Vec<Xid> = client_set
+        .workspaces()
+        .filter(|ws| client_set.has_floating_windows(ws.tag()))
+        .flat_map(|ws| ws.clients().copied())
+        .collect();
+    if clients.is_empty() {
+        return HashSet::new();
+    }
+    let mut copy: ClientSet = client_set.clone();
+    clients
+        .into_iter()
+        .filter(|id| copy.sink(id).is_some())
+        .collect()
+}
+
+fn geometry(r: Rect) -> Geometry {
+    Geometry {
+        x: r.x as i32,
+        y: r.y as i32,
+        width: r.w,
+        height: r.h,
+    }
+}
+
+// The state the queries of the socket return.
+pub fn tree(state: &State<RustConn>, x: &RustConn, floating: &HashSet<Xid>) -> Result<Tree> {
+    let cs = &state.client_set;
+    let names: Vec<String> = screen_outputs(state, x)?;
+    let mut outputs: Vec<Output> = cs
+        .screens()
+        .map(|s| Output {
+            index: s.index(),
+            name: names.get(s.index()).cloned().unwrap_or_default(),
+            geometry: geometry(s.geometry()),
+            workspace: s.workspace.tag().to_string(),
+            focused: s.index() == cs.current_screen().index(),
+        })
+        .collect();
+    outputs.sort_by_key(|o| o.index);
+
+    let focused: Option<Xid> = cs.current_client().copied();
+    let mut workspaces: Vec<Workspace> = vec![];
+    for ws in cs.workspaces() {
+        let mut clients: Vec<Client> = vec![];
+        for id in ws.clients() {
+            let mut class = match x.get_prop(*id, "WM_CLASS") {
+                Ok(Some(Prop::UTF8String(strings))) => strings.into_iter(),
+                _ => vec![].into_iter(),
+            };
+            clients.push(Client {
+                window: **id,
+                instance: class.next().unwrap_or_default(),
+                class: class.next().unwrap_or_default(),
+                title: x.window_title(*id).unwrap_or_default(),
+                pid: x.window_pid(*id),
+                floating: floating.contains(id),
+                focused: focused == Some(*id),
+                geometry: geometry(x.client_geometry(*id)?),
+            });
+        }
+        workspaces.push(Workspace {
+            name: ws.tag().to_string(),
+            layout: ws.layout_name(),
+            output: outputs
+                .iter()
+                .find(|o| o.workspace == ws.tag())
+                .map(|o| o.index),
+            focused: ws.tag() == cs.current_tag(),
+            clients,
+        });
+    }
+    // penrose keeps its workspaces in the order of the screens showing them
+    workspaces.sort_by_key(|ws| state.config.tags.iter().position(|tag| *tag == ws.name));
+    Ok(Tree {
+        outputs,
+        workspaces,
+        focused: focused.map(|id| *id),
+    })
+}
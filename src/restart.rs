@@ -0,0 +1,159 @@
+use crate::layout::Rect;
+use crate::util::private_dir;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+// Tells the new process where the state of the one it replaced was saved.
+const STATE_VARIABLE: &str = "HIPPOWM_RESTART_STATE";
+
+// What a restart keeps: where every client is, what has focus and how it is tiled.
+// Clients are identified by their window, which stays the same across the restart.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedState {
+    // name of the focused workspace
+    pub current: String,
+    // name of the workspace shown on every screen, by screen index
+    pub screens: Vec<String>,
+    pub workspaces: Vec<SavedWorkspace>,
+    // frame geometry of the floating clients
+    pub floating: HashMap<u32, Rect>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedWorkspace {
+    pub name: String,
+    // clients in stack order
+    pub clients: Vec<u32>,
+    pub focused: Option<u32>,
+    // name of the active layout, as returned by Layout::name
+    pub layout: String,
+    // main area settings of the layouts of the workspace, the ones missing keep the
+    // settings of the config
+    #[serde(default)]
+    pub layouts: Vec<SavedLayout>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedLayout {
+    pub name: String,
+    pub max_main: u32,
+    pub ratio: f32,
+}
+
+impl SavedState {
+    // name of the workspace the client was on, None if it was not managed
+    pub fn workspace_of(&self, w: u32) -> Option<&str> {
+        self.workspaces
+            .iter()
+            .find(|ws| ws.clients.contains(&w))
+            .map(|ws| ws.name.as_str())
+    }
+}
+
+// Writes the state to a file only we can read in the private runtime directory, which the
+// new process removes again.
+pub fn save(state: &SavedState) -> Result<PathBuf, String> {
+    let dir: PathBuf =
+        private_dir().map_err(|error| format!("Could not save the state: {}", error))?;
+    let path: PathBuf = dir.join(format!("hippowm-{}.yaml", process::id()));
+    let content: String = serde_yaml::to_string(state)
+        .map_err(|error| format!("Could not serialise the state: {}", error))?;
+    // left over by a process which had the same pid and never restarted
+    let _ = fs::remove_file(&path);
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)
+        .and_then(|mut file| file.write_all(content.as_bytes()))
+        .map_err(|error| format!("Could not save the state to {}: {}", path.display(), error))?;
+    info!("Saved the state to {}", path.display());
+    Ok(path)
+}
+
+// Replaces the process with a new one started the same way, which finds the saved state
+// through STATE_VARIABLE. The program is looked up again, so a rebuilt binary is used.
+// Only returns if the exec failed.
+pub fn exec(path: &Path) -> String {
+    let mut args = env::args_os();
+    let program: OsString = args.next().unwrap_or_else(|| OsString::from("hippowm"));
+    info!("Restarting {}", program.to_string_lossy());
+    let error: io::Error = Command::new(&program)
+        .args(args)
+        .env(STATE_VARIABLE, path)
+        .exec();
+    format!("Could not restart {}: {}", program.to_string_lossy(), error)
+}
+
+// The state the process we replaced saved, None if this is not a restart. The file and the
+// variable are removed, so neither a later restart nor the programs we spawn see them.
+pub fn take() -> Option<SavedState> {
+    let path: PathBuf = PathBuf::from(env::var_os(STATE_VARIABLE)?);
+    env::remove_var(STATE_VARIABLE);
+    let content: Result<String, io::Error> = fs::read_to_string(&path);
+    let _ = fs::remove_file(&path);
+    let state: Result<SavedState, String> = content
+        .map_err(|error| error.to_string())
+        .and_then(|content| serde_yaml::from_str(&content).map_err(|error| error.to_string()));
+    match state {
+        Ok(state) => {
+            info!("Restoring the state saved in {}", path.display());
+            Some(state)
+        }
+        Err(error) => {
+            warn!(
+                "Could not restore the state saved in {}: {}",
+                path.display(),
+                error
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_survives_serialisation() {
+        let state: SavedState = SavedState {
+            current: "2".to_string(),
+            screens: vec!["2".to_string(), "1".to_string()],
+            workspaces: vec![
+                SavedWorkspace {
+                    name: "1".to_string(),
+                    clients: vec![4194310, 6291462],
+                    focused: Some(6291462),
+                    layout: "Side".to_string(),
+                    layouts: vec![SavedLayout {
+                        name: "Side".to_string(),
+                        max_main: 2,
+                        ratio: 0.55,
+                    }],
+                },
+                SavedWorkspace {
+                    name: "2".to_string(),
+                    clients: vec![],
+                    focused: None,
+                    layout: "Mono".to_string(),
+                    layouts: vec![],
+                },
+            ],
+            floating: HashMap::from([(6291462, Rect::new(-20, 40, 640, 480))]),
+        };
+        let content: String = serde_yaml::to_string(&state).unwrap();
+        assert_eq!(serde_yaml::from_str::<SavedState>(&content).unwrap(), state);
+        assert_eq!(state.workspace_of(6291462), Some("1"));
+        assert_eq!(state.workspace_of(1), None);
+    }
+}
@@ -0,0 +1,234 @@
+use log::warn;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+// A window rule from the config. A rule applies to a new window when every matcher it
+// sets matches, the effects of all matching rules are combined with later rules winning.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct Rule {
+    // instance and class from WM_CLASS, compared exactly
+    pub instance: Option<String>,
+    pub class: Option<String>,
+    // regular expression searched in _NET_WM_NAME or WM_NAME
+    pub title: Option<String>,
+    // WM_WINDOW_ROLE, compared exactly
+    pub role: Option<String>,
+    // one of _NET_WM_WINDOW_TYPE, either the full atom name or its suffix like "dialog"
+    pub window_type: Option<String>,
+
+    // name of the workspace the window is put on
+    pub workspace: Option<String>,
+    // index of the monitor whose workspace the window is put on, if no workspace is given
+    pub monitor: Option<usize>,
+    pub floating: Option<bool>,
+    // position and size of a floating window, relative to its monitor
+    pub geometry: Option<Geometry>,
+    pub fullscreen: Option<bool>,
+    pub no_border: Option<bool>,
+    // false keeps the focus where it is when the window is mapped
+    pub focus: Option<bool>,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Geometry {
+    pub x: i32,
+    pub y: i32,
+    pub w: u32,
+    pub h: u32,
+}
+
+// The properties of a window that rules are matched against.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct WindowProps {
+    pub instance: String,
+    pub class: String,
+    pub title: String,
+    pub role: String,
+    pub types: Vec<String>,
+}
+
+// What the matching rules ask for, None where no rule said anything.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Effects {
+    pub workspace: Option<String>,
+    pub monitor: Option<usize>,
+    pub floating: Option<bool>,
+    pub geometry: Option<Geometry>,
+    pub fullscreen: Option<bool>,
+    pub no_border: Option<bool>,
+    pub focus: Option<bool>,
+}
+
+impl Effects {
+    // whether the window floats, given whether it would float without any rule
+    pub fn is_floating(&self, floating: bool) -> bool {
+        self.floating.unwrap_or(floating || self.geometry.is_some())
+    }
+}
+
+// The rules of the config with their title expressions compiled.
+#[derive(Default, Clone)]
+pub struct Rules {
+    rules: Vec<(Rule, Option<Regex>)>,
+}
+
+impl Rules {
+    // Rules with an invalid title expression are left out.
+    pub fn new(rules: &[Rule]) -> Self {
+        let mut compiled: Vec<(Rule, Option<Regex>)> = vec![];
+        for rule in rules {
+            let title: Option<Regex> = match &rule.title {
+                Some(title) => match Regex::new(title) {
+                    Ok(regex) => Some(regex),
+                    Err(error) => {
+                        warn!("Ignoring rule with invalid title {:?}: {}", title, error);
+                        continue;
+                    }
+                },
+                None => None,
+            };
+            compiled.push((rule.clone(), title));
+        }
+        Rules { rules: compiled }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn effects(&self, props: &WindowProps) -> Effects {
+        let mut effects: Effects = Effects::default();
+        for (rule, title) in &self.rules {
+            if !matches(rule, title.as_ref(), props) {
+                continue;
+            }
+            effects.workspace = rule.workspace.clone().or(effects.workspace);
+            effects.monitor = rule.monitor.or(effects.monitor);
+            effects.floating = rule.floating.or(effects.floating);
+            effects.geometry = rule.geometry.or(effects.geometry);
+            effects.fullscreen = rule.fullscreen.or(effects.fullscreen);
+            effects.no_border = rule.no_border.or(effects.no_border);
+            effects.focus = rule.focus.or(effects.focus);
+        }
+        effects
+    }
+}
+
+fn matches(rule: &Rule, title: Option<&Regex>, props: &WindowProps) -> bool {
+    let equals = |expected: &Option<String>, value: &str| -> bool {
+        expected.is_none() || expected.as_deref() == Some(value)
+    };
+    if !equals(&rule.instance, &props.instance)
+        || !equals(&rule.class, &props.class)
+        || !equals(&rule.role, &props.role)
+    {
+        return false;
+    }
+    if let Some(title) = title {
+        if !title.is_match(&props.title) {
+            return false;
+        }
+    }
+    if let Some(window_type) = &rule.window_type {
+        let atom: String = format!("_NET_WM_WINDOW_TYPE_{}", window_type.to_uppercase());
+        return props.types.iter().any(|t| t == window_type || *t == atom);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn firefox() -> WindowProps {
+        WindowProps {
+            instance: "Navigator".into(),
+            class: "firefox".into(),
+            title: "Rust - Mozilla Firefox".into(),
+            role: "browser".into(),
+            types: vec!["_NET_WM_WINDOW_TYPE_NORMAL".into()],
+        }
+    }
+
+    #[test]
+    fn all_matchers_have_to_match() {
+        let rules: Rules = Rules::new(&[
+            Rule {
+                class: Some("firefox".into()),
+                title: Some("Firefox$".into()),
+                workspace: Some("2".into()),
+                ..Rule::default()
+            },
+            Rule {
+                class: Some("firefox".into()),
+                role: Some("dialog".into()),
+                floating: Some(true),
+                ..Rule::default()
+            },
+        ]);
+        let effects: Effects = rules.effects(&firefox());
+        assert_eq!(effects.workspace.as_deref(), Some("2"));
+        assert_eq!(effects.floating, None);
+    }
+
+    #[test]
+    fn later_rules_win() {
+        let rules: Rules = Rules::new(&[
+            Rule {
+                floating: Some(true),
+                focus: Some(false),
+                ..Rule::default()
+            },
+            Rule {
+                instance: Some("Navigator".into()),
+                floating: Some(false),
+                ..Rule::default()
+            },
+        ]);
+        let effects: Effects = rules.effects(&firefox());
+        assert_eq!(effects.floating, Some(false));
+        assert_eq!(effects.focus, Some(false));
+    }
+
+    #[test]
+    fn window_types_match_by_suffix() {
+        let rule = |window_type: &str| -> Rules {
+            Rules::new(&[Rule {
+                window_type: Some(window_type.into()),
+                fullscreen: Some(true),
+                ..Rule::default()
+            }])
+        };
+        assert_eq!(rule("normal").effects(&firefox()).fullscreen, Some(true));
+        assert_eq!(
+            rule("_NET_WM_WINDOW_TYPE_NORMAL")
+                .effects(&firefox())
+                .fullscreen,
+            Some(true)
+        );
+        assert_eq!(rule("dialog").effects(&firefox()).fullscreen, None);
+    }
+
+    #[test]
+    fn invalid_titles_are_ignored() {
+        let rules: Rules = Rules::new(&[Rule {
+            title: Some("(".into()),
+            ..Rule::default()
+        }]);
+        assert!(rules.is_empty());
+    }
+
+    #[test]
+    fn geometry_implies_floating() {
+        let effects: Effects = Effects {
+            geometry: Some(Geometry {
+                x: 0,
+                y: 0,
+                w: 100,
+                h: 100,
+            }),
+            ..Effects::default()
+        };
+        assert!(effects.is_floating(false));
+    }
+}
@@ -0,0 +1,233 @@
+use crate::ipc::protocol::runtime_dir;
+use log::{info, warn};
+use std::error::Error;
+use std::fs::{self, DirBuilder};
+use std::io;
+use std::mem;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::PathBuf;
+use std::process;
+use std::ptr;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::thread;
+use std::time::Duration;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ClientMessageEvent, ConnectionExt, EventMask, Window,
+};
+
+// Kills a client which did not close its window after being asked to. The check runs on
+// its own thread and connection, so neither backend blocks while waiting. If the window
+// is gone by the time the timeout expired, nothing happens.
+pub fn force_kill_after(display: Option<String>, window: u32, pid: Option<u32>, timeout: Duration) {
+    thread::spawn(move || {
+        thread::sleep(timeout);
+        if let Err(error) = force_kill(display, window, pid) {
+            warn!("Could not kill the client of window {}: {}", window, error);
+        }
+    });
+}
+
+// The process is only killed if it runs on this host and still owns the window, remote
+// clients and clients without a pid are disconnected from the server instead.
+fn force_kill(
+    display: Option<String>,
+    window: u32,
+    pid: Option<u32>,
+) -> Result<(), Box<dyn Error>> {
+    let (conn, _) = x11rb::connect(display.as_deref())?;
+    if conn.get_window_attributes(window)?.reply().is_err() {
+        return Ok(());
+    }
+    let pid_atom: Atom = conn.intern_atom(false, b"_NET_WM_PID")?.reply()?.atom;
+    let current: Option<u32> = conn
+        .get_property(false, window, pid_atom, AtomEnum::CARDINAL, 0, 1)?
+        .reply()?
+        .value32()
+        .and_then(|mut values| values.next());
+    let machine = conn
+        .get_property(
+            false,
+            window,
+            AtomEnum::WM_CLIENT_MACHINE,
+            AtomEnum::ANY,
+            0,
+            u32::MAX,
+        )?
+        .reply()?;
+    match local_pid(pid, current, &machine.value, hostname().as_deref()) {
+        Some(pid) => {
+            info!(
+                "Window {} did not close in time, sending SIGKILL to {}",
+                window, pid
+            );
+            if unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) } != 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+        }
+        None => {
+            info!(
+                "Window {} did not close in time, disconnecting its client",
+                window
+            );
+            conn.kill_client(window)?;
+            conn.flush()?;
+        }
+    }
+    Ok(())
+}
+
+// The pid which is safe to kill: the one read when the window was closed, if the window
+// still has it and WM_CLIENT_MACHINE names this host. Otherwise the pid may belong to a
+// process on another host, or to an unrelated one after the client lied or exited.
+fn local_pid(
+    pid: Option<u32>,
+    current: Option<u32>,
+    machine: &[u8],
+    hostname: Option<&str>,
+) -> Option<u32> {
+    let machine: &[u8] = machine.strip_suffix(b"\0").unwrap_or(machine);
+    match (pid, hostname) {
+        (Some(pid), Some(hostname)) if current == Some(pid) && machine == hostname.as_bytes() => {
+            Some(pid)
+        }
+        _ => None,
+    }
+}
+
+// The name of this host, as clients write it to WM_CLIENT_MACHINE.
+fn hostname() -> Option<String> {
+    let mut buffer: [u8; 256] = [0; 256];
+    if unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) } != 0 {
+        return None;
+    }
+    let len: usize = buffer.iter().position(|b| *b == 0).unwrap_or(buffer.len());
+    Some(String::from_utf8_lossy(&buffer[..len]).into_owned())
+}
+
+// Type of the ClientMessage sent to root once a shutdown signal arrived. Both backends
+// block in their event loop, so the signal is turned into an X event which wakes them up.
+pub const SHUTDOWN_MESSAGE: &str = "_HIPPOWM_SHUTDOWN";
+
+// The signal which asked us to shut down, 0 if there was none.
+static SIGNAL: AtomicI32 = AtomicI32::new(0);
+// Write end of the pipe the signal handler wakes the shutdown thread with.
+static SIGNAL_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+// Only async-signal-safe calls are allowed here, everything else is left to the thread.
+extern "C" fn on_signal(signal: libc::c_int) {
+    SIGNAL.store(signal, Ordering::SeqCst);
+    let byte: u8 = 0;
+    unsafe {
+        libc::write(
+            SIGNAL_PIPE.load(Ordering::SeqCst),
+            &byte as *const u8 as *const libc::c_void,
+            1,
+        );
+    }
+}
+
+// Sends SHUTDOWN_MESSAGE to root on SIGTERM, SIGINT and SIGHUP, so the window manager can
+// give its clients back to root before exiting. If that message can not be sent the
+// process exits right away, the same way it would have without a handler.
+pub fn notify_on_signals(display: Option<String>) -> io::Result<()> {
+    let mut fds: [libc::c_int; 2] = [-1; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let (read_end, write_end) = (fds[0], fds[1]);
+    // programs we spawn must not inherit the pipe
+    for fd in fds {
+        unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+    }
+    SIGNAL_PIPE.store(write_end, Ordering::SeqCst);
+
+    for signal in [libc::SIGTERM, libc::SIGINT, libc::SIGHUP] {
+        let mut action: libc::sigaction = unsafe { mem::zeroed() };
+        action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        unsafe { libc::sigemptyset(&mut action.sa_mask) };
+        if unsafe { libc::sigaction(signal, &action, ptr::null_mut()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    thread::spawn(move || {
+        let mut byte: u8 = 0;
+        while unsafe { libc::read(read_end, &mut byte as *mut u8 as *mut libc::c_void, 1) } != 1 {}
+        let signal: i32 = SIGNAL.load(Ordering::SeqCst);
+        info!("Received signal {}, shutting down", signal);
+        if let Err(error) = send_shutdown(display.as_deref()) {
+            warn!("Could not ask the window manager to shut down: {}", error);
+            process::exit(exit_code());
+        }
+    });
+    Ok(())
+}
+
+// Sends SHUTDOWN_MESSAGE to root on a connection of our own.
+fn send_shutdown(display: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let (conn, screen) = x11rb::connect(display)?;
+    let root: Window = conn.setup().roots[screen].root;
+    let atom: Atom = conn
+        .intern_atom(false, SHUTDOWN_MESSAGE.as_bytes())?
+        .reply()?
+        .atom;
+    let event = ClientMessageEvent::new(32, root, atom, [0u32; 5]);
+    conn.send_event(false, root, EventMask::SUBSTRUCTURE_NOTIFY, event)?;
+    conn.flush()?;
+    Ok(())
+}
+
+// The exit code for a shutdown, 128 plus the signal as shells report it, or 0 if the
+// window manager was not stopped by a signal.
+pub fn exit_code() -> i32 {
+    match SIGNAL.load(Ordering::SeqCst) {
+        0 => 0,
+        signal => 128 + signal,
+    }
+}
+
+// The runtime directory, created if it is missing. It has to belong to us and be private,
+// otherwise other users could plant files in it or replace ours with symlinks.
+pub fn private_dir() -> io::Result<PathBuf> {
+    let dir: PathBuf = runtime_dir();
+    match DirBuilder::new().mode(0o700).create(&dir) {
+        Ok(()) => {}
+        Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(error) => return Err(error),
+    }
+    let metadata = fs::symlink_metadata(&dir)?;
+    if !metadata.is_dir()
+        || metadata.uid() != unsafe { libc::getuid() }
+        || metadata.mode() & 0o077 != 0
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not a private directory", dir.display()),
+        ));
+    }
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_local_clients_which_kept_their_pid_are_killed() {
+        assert_eq!(local_pid(Some(42), Some(42), b"box", Some("box")), Some(42));
+        assert_eq!(
+            local_pid(Some(42), Some(42), b"box\0", Some("box")),
+            Some(42)
+        );
+        // forwarded from another host
+        assert_eq!(local_pid(Some(42), Some(42), b"remote", Some("box")), None);
+        assert_eq!(local_pid(Some(42), Some(42), b"", Some("box")), None);
+        // the pid changed while we waited
+        assert_eq!(local_pid(Some(42), Some(43), b"box", Some("box")), None);
+        assert_eq!(local_pid(Some(42), None, b"box", Some("box")), None);
+        assert_eq!(local_pid(None, None, b"box", Some("box")), None);
+        assert_eq!(local_pid(Some(42), Some(42), b"box", None), None);
+    }
+}
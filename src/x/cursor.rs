@@ -0,0 +1,267 @@
+use crate::config::Config;
+use crate::x::error::XwmError;
+use log::{info, warn};
+use std::env;
+use std::fs;
+use x11rb::connection::Connection;
+use x11rb::protocol::render::{self, ConnectionExt as _, CreatePictureAux, PictType};
+use x11rb::protocol::xproto::{
+    AtomEnum, ConnectionExt as _, CreateGCAux, Cursor, ImageFormat, Pixmap, Window,
+};
+use xcursor::parser::{parse_xcursor, Image};
+use xcursor::CursorTheme;
+
+// Size of the cursor if neither the config, the environment nor Xresources set one.
+const DEFAULT_SIZE: u32 = 24;
+
+// Glyphs of the core cursor font, used if the theme has no such cursor.
+const XC_BOTTOM_RIGHT_CORNER: u16 = 14;
+const XC_FLEUR: u16 = 52;
+const XC_LEFT_PTR: u16 = 68;
+
+// The cursors Xwm shows: the normal one on root and the ones for dragging with the mouse.
+#[derive(Clone, Copy, Debug)]
+pub struct Cursors {
+    pub normal: Cursor,
+    pub moving: Cursor,
+    pub resizing: Cursor,
+}
+
+impl Cursors {
+    // Loads the cursors from the cursor theme of the user.
+    pub fn load(conn: &impl Connection, root: Window, config: &Config) -> Result<Self, XwmError> {
+        let resources: String = resource_database(conn, root)?;
+        let name: String = config
+            .cursor_theme
+            .clone()
+            .or_else(|| env::var("XCURSOR_THEME").ok())
+            .or_else(|| resource(&resources, "Xcursor.theme"))
+            .unwrap_or_else(|| "default".to_string());
+        let size: u32 = config
+            .cursor_size
+            .or_else(|| env::var("XCURSOR_SIZE").ok()?.parse().ok())
+            .or_else(|| resource(&resources, "Xcursor.size")?.parse().ok())
+            .unwrap_or(DEFAULT_SIZE);
+        info!("Using cursor theme {} at size {}", name, size);
+
+        let theme: CursorTheme = CursorTheme::load(&name);
+        let format: Option<render::Pictformat> = argb_format(conn)?;
+        let load = |names: &[&str], glyph: u16| -> Result<Cursor, XwmError> {
+            load_cursor(conn, root, &theme, format, names, size, glyph)
+        };
+        Ok(Cursors {
+            normal: load(&["left_ptr", "default"], XC_LEFT_PTR)?,
+            moving: load(&["fleur", "move", "grabbing"], XC_FLEUR)?,
+            resizing: load(
+                &["bottom_right_corner", "se-resize", "nwse-resize"],
+                XC_BOTTOM_RIGHT_CORNER,
+            )?,
+        })
+    }
+}
+
+// Loads the first of the given cursors the theme has, or the glyph of the core cursor font.
+fn load_cursor(
+    conn: &impl Connection,
+    root: Window,
+    theme: &CursorTheme,
+    format: Option<render::Pictformat>,
+    names: &[&str],
+    size: u32,
+    glyph: u16,
+) -> Result<Cursor, XwmError> {
+    if let Some(format) = format {
+        for name in names {
+            let images: Vec<Image> = match theme
+                .load_icon(name)
+                .and_then(|path| fs::read(path).ok())
+                .and_then(|content| parse_xcursor(&content))
+            {
+                Some(images) => images,
+                None => continue,
+            };
+            if let Some(image) = nearest_image(&images, size) {
+                return create_image_cursor(conn, root, format, image);
+            }
+        }
+        warn!(
+            "Cursor theme has none of {:?}, using the core cursor",
+            names
+        );
+    }
+    create_glyph_cursor(conn, glyph)
+}
+
+// The first image of the size closest to the requested one, animations are not played.
+fn nearest_image(images: &[Image], size: u32) -> Option<&Image> {
+    images
+        .iter()
+        .min_by_key(|image| (image.size as i64 - size as i64).abs())
+}
+
+// The 32 bit ARGB picture format of RENDER, None if the server can not do ARGB cursors.
+fn argb_format(conn: &impl Connection) -> Result<Option<render::Pictformat>, XwmError> {
+    if conn
+        .extension_information(render::X11_EXTENSION_NAME)?
+        .is_none()
+    {
+        return Ok(None);
+    }
+    conn.render_query_version(0, 11)?.reply()?;
+    let formats = conn.render_query_pict_formats()?.reply()?;
+    Ok(formats
+        .formats
+        .iter()
+        .find(|f| {
+            f.type_ == PictType::DIRECT
+                && f.depth == 32
+                && f.direct.alpha_shift == 24
+                && f.direct.red_shift == 16
+                && f.direct.green_shift == 8
+                && f.direct.blue_shift == 0
+        })
+        .map(|f| f.id))
+}
+
+// Uploads an image of the theme to a pixmap and makes a cursor of it.
+fn create_image_cursor(
+    conn: &impl Connection,
+    root: Window,
+    format: render::Pictformat,
+    image: &Image,
+) -> Result<Cursor, XwmError> {
+    let (width, height) = (image.width as u16, image.height as u16);
+    let pixmap: Pixmap = conn.generate_id()?;
+    conn.create_pixmap(32, pixmap, root, width, height)?;
+    let gc = conn.generate_id()?;
+    conn.create_gc(gc, pixmap, &CreateGCAux::new())?;
+    let data: Vec<u8> = native_pixels(&image.pixels_rgba);
+    conn.put_image(
+        ImageFormat::Z_PIXMAP,
+        pixmap,
+        gc,
+        width,
+        height,
+        0,
+        0,
+        0,
+        32,
+        &data,
+    )?;
+    let picture = conn.generate_id()?;
+    conn.render_create_picture(picture, pixmap, format, &CreatePictureAux::new())?;
+    let cursor: Cursor = conn.generate_id()?;
+    conn.render_create_cursor(cursor, picture, image.xhot as u16, image.yhot as u16)?;
+    conn.render_free_picture(picture)?;
+    conn.free_gc(gc)?;
+    conn.free_pixmap(pixmap)?;
+    Ok(cursor)
+}
+
+// Xcursor files store the pixels as little endian ARGB32 words and xcursor keeps these
+// bytes as they are in pixels_rgba, despite its name. put_image takes the words of a 32
+// bit ZPixmap in the byte order of the client, so every word is converted to that one.
+fn native_pixels(file_bytes: &[u8]) -> Vec<u8> {
+    file_bytes
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .flat_map(u32::to_ne_bytes)
+        .collect()
+}
+
+// A black and white cursor of the core cursor font.
+fn create_glyph_cursor(conn: &impl Connection, glyph: u16) -> Result<Cursor, XwmError> {
+    let font = conn.generate_id()?;
+    conn.open_font(font, b"cursor")?;
+    let cursor: Cursor = conn.generate_id()?;
+    conn.create_glyph_cursor(
+        cursor,
+        font,
+        font,
+        glyph,
+        glyph + 1,
+        0,
+        0,
+        0,
+        0xffff,
+        0xffff,
+        0xffff,
+    )?;
+    conn.close_font(font)?;
+    Ok(cursor)
+}
+
+// The Xresources loaded with xrdb, which are kept in RESOURCE_MANAGER on root.
+fn resource_database(conn: &impl Connection, root: Window) -> Result<String, XwmError> {
+    let reply = conn
+        .get_property(
+            false,
+            root,
+            AtomEnum::RESOURCE_MANAGER,
+            AtomEnum::STRING,
+            0,
+            u32::MAX,
+        )?
+        .reply()?;
+    Ok(String::from_utf8_lossy(&reply.value).into_owned())
+}
+
+// Looks up a resource like "Xcursor.theme" in the lines of an Xresources database,
+// "*theme" style wildcards are not supported.
+fn resource(database: &str, name: &str) -> Option<String> {
+    database.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        if key.trim() != name {
+            return None;
+        }
+        Some(value.trim().to_string()).filter(|value| !value.is_empty())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(size: u32) -> Image {
+        Image {
+            size,
+            width: size,
+            height: size,
+            xhot: 0,
+            yhot: 0,
+            delay: 0,
+            pixels_rgba: vec![],
+            pixels_argb: vec![],
+        }
+    }
+
+    #[test]
+    fn resources_are_found_by_name() {
+        let database: &str = "Xft.dpi:\t96\nXcursor.theme:\tAdwaita\nXcursor.size:\t32\n";
+        assert_eq!(
+            resource(database, "Xcursor.theme").as_deref(),
+            Some("Adwaita")
+        );
+        assert_eq!(resource(database, "Xcursor.size").as_deref(), Some("32"));
+        assert_eq!(resource(database, "Xcursor.foo"), None);
+    }
+
+    #[test]
+    fn pixels_are_converted_to_native_argb_words() {
+        // one opaque red and one half transparent blue pixel, as stored in the file
+        let file_bytes: [u8; 8] = [0x00, 0x00, 0xff, 0xff, 0x80, 0x00, 0x00, 0x80];
+        let words: Vec<u32> = native_pixels(&file_bytes)
+            .chunks_exact(4)
+            .map(|word| u32::from_ne_bytes([word[0], word[1], word[2], word[3]]))
+            .collect();
+        assert_eq!(words, [0xffff0000, 0x80000080]);
+    }
+
+    #[test]
+    fn nearest_image_size_is_used() {
+        let images: Vec<Image> = vec![image(24), image(32), image(48)];
+        assert_eq!(nearest_image(&images, 30).map(|i| i.size), Some(32));
+        assert_eq!(nearest_image(&images, 96).map(|i| i.size), Some(48));
+        assert_eq!(nearest_image(&[], 24).map(|i| i.size), None);
+    }
+}
@@ -0,0 +1,104 @@
+use std::error::Error;
+use std::fmt;
+use x11rb::errors::{ConnectError, ConnectionError, ReplyError, ReplyOrIdError};
+use x11rb::x11_utils::X11Error;
+
+// Everything that can go wrong in Xwm.
+#[derive(Debug)]
+pub enum XwmError {
+    // The display could not be opened.
+    DisplayOpen {
+        display: String,
+        reason: ConnectError,
+    },
+    // Another window manager already selected SubstructureRedirect on the root window.
+    WmDetected(String),
+    // The connection to the X server broke, there is no way to recover from this.
+    Connection(ConnectionError),
+    // All resource ids of the connection are in use, so no new frame can be created.
+    IdsExhausted,
+    // An error reported by the X server for one of our requests.
+    Protocol {
+        // name of the failed request, e.g. "ConfigureWindow"
+        request: String,
+        // kind of the error, e.g. "Window"
+        error: String,
+        resource: u64,
+    },
+}
+
+impl XwmError {
+    // Protocol errors concern a single request, everything else ends the event loop.
+    pub fn is_fatal(&self) -> bool {
+        !matches!(self, XwmError::Protocol { .. })
+    }
+}
+
+impl fmt::Display for XwmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XwmError::DisplayOpen { display, reason } => {
+                write!(f, "Could not open X display {}: {}", display, reason)
+            }
+            XwmError::WmDetected(display) => {
+                write!(
+                    f,
+                    "Another window manager is already running on display {}",
+                    display
+                )
+            }
+            XwmError::Connection(error) => write!(f, "Lost connection to the X server: {}", error),
+            XwmError::IdsExhausted => write!(f, "Ran out of X resource ids"),
+            XwmError::Protocol {
+                request,
+                error,
+                resource,
+            } => write!(
+                f,
+                "X error {} for request {} on resource {}",
+                error, request, resource
+            ),
+        }
+    }
+}
+
+impl Error for XwmError {}
+
+impl From<X11Error> for XwmError {
+    fn from(error: X11Error) -> Self {
+        let request: String = match error.request_name {
+            Some(name) => name.to_string(),
+            None => format!("{}:{}", error.major_opcode, error.minor_opcode),
+        };
+        XwmError::Protocol {
+            request,
+            error: format!("{:?}", error.error_kind),
+            resource: error.bad_value as u64,
+        }
+    }
+}
+
+impl From<ConnectionError> for XwmError {
+    fn from(error: ConnectionError) -> Self {
+        XwmError::Connection(error)
+    }
+}
+
+impl From<ReplyError> for XwmError {
+    fn from(error: ReplyError) -> Self {
+        match error {
+            ReplyError::ConnectionError(error) => XwmError::Connection(error),
+            ReplyError::X11Error(error) => error.into(),
+        }
+    }
+}
+
+impl From<ReplyOrIdError> for XwmError {
+    fn from(error: ReplyOrIdError) -> Self {
+        match error {
+            ReplyOrIdError::ConnectionError(error) => XwmError::Connection(error),
+            ReplyOrIdError::X11Error(error) => error.into(),
+            ReplyOrIdError::IdsExhausted => XwmError::IdsExhausted,
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use crate::x::error::XwmError;
+use crate::x::xwm::Atoms;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    AtomEnum, ConnectionExt as _, CreateWindowAux, PropMode, Window, WindowClass,
+};
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::NONE;
+
+// Name Xwm announces itself with on the supporting wm check window.
+const WM_NAME: &str = "HippoWM";
+
+// Creates the window _NET_SUPPORTING_WM_CHECK points to and announces the supported hints,
+// which tells pagers, bars and launchers that an EWMH compliant window manager is running.
+pub fn announce(conn: &RustConnection, root: Window, atoms: &Atoms) -> Result<Window, XwmError> {
+    let check: Window = conn.generate_id()?;
+    conn.create_window(
+        0,
+        check,
+        root,
+        -1,
+        -1,
+        1,
+        1,
+        0,
+        WindowClass::INPUT_ONLY,
+        x11rb::COPY_FROM_PARENT,
+        &CreateWindowAux::new(),
+    )?;
+    for window in [root, check] {
+        conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            atoms._NET_SUPPORTING_WM_CHECK,
+            AtomEnum::WINDOW,
+            &[check],
+        )?;
+    }
+    conn.change_property8(
+        PropMode::REPLACE,
+        check,
+        atoms._NET_WM_NAME,
+        atoms.UTF8_STRING,
+        WM_NAME.as_bytes(),
+    )?;
+
+    let supported: [u32; 19] = [
+        atoms._NET_SUPPORTED,
+        atoms._NET_SUPPORTING_WM_CHECK,
+        atoms._NET_WM_NAME,
+        atoms._NET_CLIENT_LIST,
+        atoms._NET_CLIENT_LIST_STACKING,
+        atoms._NET_ACTIVE_WINDOW,
+        atoms._NET_NUMBER_OF_DESKTOPS,
+        atoms._NET_DESKTOP_NAMES,
+        atoms._NET_CURRENT_DESKTOP,
+        atoms._NET_CLOSE_WINDOW,
+        atoms._NET_WM_DESKTOP,
+        atoms._NET_WM_PID,
+        atoms._NET_WM_WINDOW_TYPE,
+        atoms._NET_WM_WINDOW_TYPE_DOCK,
+        atoms._NET_WM_WINDOW_TYPE_DIALOG,
+        atoms._NET_WM_WINDOW_TYPE_UTILITY,
+        atoms._NET_WM_WINDOW_TYPE_SPLASH,
+        atoms._NET_WM_STRUT,
+        atoms._NET_WM_STRUT_PARTIAL,
+    ];
+    conn.change_property32(
+        PropMode::REPLACE,
+        root,
+        atoms._NET_SUPPORTED,
+        AtomEnum::ATOM,
+        &supported,
+    )?;
+    Ok(check)
+}
+
+// Publishes the number and names of the workspaces.
+pub fn set_desktops(
+    conn: &RustConnection,
+    root: Window,
+    atoms: &Atoms,
+    names: &[String],
+) -> Result<(), XwmError> {
+    conn.change_property32(
+        PropMode::REPLACE,
+        root,
+        atoms._NET_NUMBER_OF_DESKTOPS,
+        AtomEnum::CARDINAL,
+        &[names.len() as u32],
+    )?;
+    // the names are null terminated strings, one after another
+    let mut data: Vec<u8> = vec![];
+    for name in names {
+        data.extend_from_slice(name.as_bytes());
+        data.push(0);
+    }
+    conn.change_property8(
+        PropMode::REPLACE,
+        root,
+        atoms._NET_DESKTOP_NAMES,
+        atoms.UTF8_STRING,
+        &data,
+    )?;
+    Ok(())
+}
+
+pub fn set_current_desktop(
+    conn: &RustConnection,
+    root: Window,
+    atoms: &Atoms,
+    index: usize,
+) -> Result<(), XwmError> {
+    conn.change_property32(
+        PropMode::REPLACE,
+        root,
+        atoms._NET_CURRENT_DESKTOP,
+        AtomEnum::CARDINAL,
+        &[index as u32],
+    )?;
+    Ok(())
+}
+
+// Publishes all clients, once in the order they were mapped and once bottom to top.
+pub fn set_client_list(
+    conn: &RustConnection,
+    root: Window,
+    atoms: &Atoms,
+    clients: &[Window],
+    stacking: &[Window],
+) -> Result<(), XwmError> {
+    conn.change_property32(
+        PropMode::REPLACE,
+        root,
+        atoms._NET_CLIENT_LIST,
+        AtomEnum::WINDOW,
+        clients,
+    )?;
+    conn.change_property32(
+        PropMode::REPLACE,
+        root,
+        atoms._NET_CLIENT_LIST_STACKING,
+        AtomEnum::WINDOW,
+        stacking,
+    )?;
+    Ok(())
+}
+
+pub fn set_active_window(
+    conn: &RustConnection,
+    root: Window,
+    atoms: &Atoms,
+    w: Option<Window>,
+) -> Result<(), XwmError> {
+    conn.change_property32(
+        PropMode::REPLACE,
+        root,
+        atoms._NET_ACTIVE_WINDOW,
+        AtomEnum::WINDOW,
+        &[w.unwrap_or(NONE)],
+    )?;
+    Ok(())
+}
+
+// Tells a client which workspace it is on.
+pub fn set_wm_desktop(
+    conn: &RustConnection,
+    w: Window,
+    atoms: &Atoms,
+    index: usize,
+) -> Result<(), XwmError> {
+    conn.change_property32(
+        PropMode::REPLACE,
+        w,
+        atoms._NET_WM_DESKTOP,
+        AtomEnum::CARDINAL,
+        &[index as u32],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,150 @@
+use crate::config::Config;
+use std::ffi::CString;
+use x11rb::protocol::xproto::{Keycode, Keysym, ModMask};
+
+// What happens when a grabbed key combination is pressed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeyAction {
+    // one of the action names understood by HippoWM::action
+    Action(String),
+    // a command which is spawned
+    Spawn(String),
+    // switches to the workspace with the given name
+    Workspace(String),
+    // moves the focused client to the workspace with the given name
+    MoveToWorkspace(String),
+}
+
+// A key combination as written in the config, e.g. "M-S-q".
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct KeyBind {
+    pub mask: u16,
+    pub keysym: Keysym,
+}
+
+// Parses a bind string the same way penrose does: modifiers separated by '-', followed by
+// the name of a keysym. M is Mod4, A is Alt, S is Shift and C is Control.
+pub fn parse_bind(bind: &str) -> Option<KeyBind> {
+    let mut parts: Vec<&str> = bind.split('-').collect();
+    let name: &str = parts.pop()?;
+
+    let mut mask: u16 = 0;
+    for modifier in parts {
+        mask |= u16::from(match modifier {
+            "M" => ModMask::M4,
+            "A" => ModMask::M1,
+            "S" => ModMask::SHIFT,
+            "C" => ModMask::CONTROL,
+            _ => return None,
+        });
+    }
+
+    let keysym: Keysym = string_to_keysym(name)?;
+    Some(KeyBind { mask, keysym })
+}
+
+// Looks up a keysym by its name, e.g. "Return". The X protocol has no request for this,
+// so the table of libX11 is used, which does not need a connection.
+fn string_to_keysym(name: &str) -> Option<Keysym> {
+    let name: CString = CString::new(name).ok()?;
+    let keysym = unsafe { x11::xlib::XStringToKeysym(name.as_ptr()) };
+    if keysym == x11::xlib::NoSymbol as x11::xlib::KeySym {
+        return None;
+    }
+    Some(keysym as Keysym)
+}
+
+// Finds all keycodes producing the keysym, given the reply of GetKeyboardMapping.
+pub fn keycodes_for(
+    keysym: Keysym,
+    min_keycode: Keycode,
+    keysyms_per_keycode: u8,
+    keysyms: &[Keysym],
+) -> Vec<Keycode> {
+    if keysyms_per_keycode == 0 {
+        return vec![];
+    }
+    keysyms
+        .chunks(keysyms_per_keycode as usize)
+        .enumerate()
+        .filter(|(_, syms)| syms.contains(&keysym))
+        .map(|(i, _)| min_keycode + i as Keycode)
+        .collect()
+}
+
+// Collects every bind of the config, in the same order HippoWM::configure inserts them,
+// so later entries win when a bind is used twice. The workspace binds come last, like
+// they do in HippoWM::ws_binds.
+pub fn key_actions(config: &Config) -> Vec<(String, KeyAction)> {
+    let mut actions: Vec<(String, KeyAction)> = vec![];
+    for a in config
+        .actions
+        .iter()
+        .chain(&config.x_actions)
+        .chain(&config.window_actions)
+    {
+        actions.push((a.bind.clone(), KeyAction::Action(a.action.to_lowercase())));
+    }
+    for cmd in config
+        .commands
+        .iter()
+        .chain(&config.x_command)
+        .chain(&config.window_commands)
+    {
+        actions.push((cmd.bind.clone(), KeyAction::Spawn(cmd.command.clone())));
+    }
+    for ws in ["1", "2", "3", "4", "5", "6", "7", "8", "9", "0"] {
+        actions.push((format!("M-{}", ws), KeyAction::Workspace(ws.to_string())));
+        actions.push((
+            format!("M-S-{}", ws),
+            KeyAction::MoveToWorkspace(ws.to_string()),
+        ));
+    }
+    actions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // keysyms of the latin letters and Return, from X11/keysymdef.h
+    const XK_Q: Keysym = 0x51;
+    const XK_LOWER_Q: Keysym = 0x71;
+    const XK_RETURN: Keysym = 0xff0d;
+
+    #[test]
+    fn modifier_aliases_are_combined() {
+        let bind: KeyBind = parse_bind("M-A-S-C-Return").unwrap();
+        let mask = ModMask::M4 | ModMask::M1 | ModMask::SHIFT | ModMask::CONTROL;
+        assert_eq!(bind.mask, u16::from(mask));
+        assert_eq!(bind.keysym, XK_RETURN);
+        assert_eq!(parse_bind("Return").map(|b| b.mask), Some(0));
+        assert_eq!(parse_bind("X-Return"), None);
+        assert_eq!(parse_bind("m-Return"), None);
+    }
+
+    #[test]
+    fn unknown_keysyms_are_rejected() {
+        assert_eq!(parse_bind("M-NoSuchKey"), None);
+        assert_eq!(parse_bind("M-"), None);
+        assert_eq!(parse_bind(""), None);
+    }
+
+    #[test]
+    fn keysym_names_keep_their_case() {
+        assert_eq!(parse_bind("M-q").map(|b| b.keysym), Some(XK_LOWER_Q));
+        assert_eq!(parse_bind("M-Q").map(|b| b.keysym), Some(XK_Q));
+        // shift is a modifier of its own, it does not change the keysym
+        assert_eq!(parse_bind("M-S-q").map(|b| b.keysym), Some(XK_LOWER_Q));
+    }
+
+    #[test]
+    fn all_keycodes_of_a_keysym_are_found() {
+        // keycodes 8 to 10 with two keysyms each, q is on 8 and 10
+        let keysyms: [Keysym; 6] = [XK_LOWER_Q, XK_Q, XK_RETURN, 0, XK_LOWER_Q, XK_Q];
+        assert_eq!(keycodes_for(XK_LOWER_Q, 8, 2, &keysyms), [8, 10]);
+        assert_eq!(keycodes_for(XK_RETURN, 8, 2, &keysyms), [9]);
+        assert!(keycodes_for(0x61, 8, 2, &keysyms).is_empty());
+        assert!(keycodes_for(XK_LOWER_Q, 8, 0, &keysyms).is_empty());
+    }
+}
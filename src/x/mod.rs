@@ -1 +1,7 @@
 pub mod xwm;
+pub mod error;
+pub mod keys;
+pub mod ewmh;
+pub mod workspace;
+pub mod monitor;
+pub mod cursor;
@@ -0,0 +1,249 @@
+use crate::layout::Rect;
+use crate::x::error::XwmError;
+use log::{info, warn};
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::{self, ConnectionExt as _, NotifyMask};
+use x11rb::protocol::xproto::{ConnectionExt as _, Window};
+
+// An active monitor as reported by RandR and the workspace shown on it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Monitor {
+    // name of the output, e.g. "HDMI-1", used to give it its workspace back after changes
+    pub name: String,
+    pub rect: Rect,
+    pub workspace: usize,
+}
+
+// Selects RRScreenChangeNotify on root, which is sent whenever outputs are connected,
+// disconnected or change their resolution. Without RandR there is nothing to select.
+pub fn select_changes(conn: &impl Connection, root: Window) -> Result<(), XwmError> {
+    if conn
+        .extension_information(randr::X11_EXTENSION_NAME)?
+        .is_none()
+    {
+        warn!("RandR is not available, using the whole root window as one monitor");
+        return Ok(());
+    }
+    conn.randr_query_version(1, 5)?.reply()?;
+    conn.randr_select_input(root, NotifyMask::SCREEN_CHANGE)?;
+    Ok(())
+}
+
+// Asks the server for the active monitors, ordered left to right and top to bottom.
+// RandR 1.5 knows monitors, older servers only have CRTCs, of which mirrored ones are
+// merged. Without RandR, or without any active output, root is the only monitor.
+pub fn query_monitors(
+    conn: &impl Connection,
+    root: Window,
+    root_rect: Rect,
+) -> Result<Vec<(String, Rect)>, XwmError> {
+    let mut monitors: Vec<(String, Rect)> = vec![];
+    if conn
+        .extension_information(randr::X11_EXTENSION_NAME)?
+        .is_some()
+    {
+        let version = conn.randr_query_version(1, 5)?.reply()?;
+        monitors = if (version.major_version, version.minor_version) >= (1, 5) {
+            query_randr_monitors(conn, root)?
+        } else {
+            query_crtcs(conn, root)?
+        };
+    }
+    if monitors.is_empty() {
+        monitors.push(("root".to_string(), root_rect));
+    }
+    monitors.sort_by_key(|(_, r)| (r.x, r.y));
+    info!("Monitors: {:?}", monitors);
+    Ok(monitors)
+}
+
+fn query_randr_monitors(
+    conn: &impl Connection,
+    root: Window,
+) -> Result<Vec<(String, Rect)>, XwmError> {
+    let infos = conn.randr_get_monitors(root, true)?.reply()?.monitors;
+    // the names are atoms, all of them are requested before the first reply is awaited
+    let cookies = infos
+        .iter()
+        .map(|m| conn.get_atom_name(m.name))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut monitors: Vec<(String, Rect)> = vec![];
+    for (m, cookie) in infos.iter().zip(cookies) {
+        let name: String = String::from_utf8_lossy(&cookie.reply()?.name).into_owned();
+        let r: Rect = Rect::new(m.x as i32, m.y as i32, m.width as u32, m.height as u32);
+        monitors.push((name, r));
+    }
+    Ok(monitors)
+}
+
+fn query_crtcs(conn: &impl Connection, root: Window) -> Result<Vec<(String, Rect)>, XwmError> {
+    let resources = conn.randr_get_screen_resources_current(root)?.reply()?;
+    let cookies = resources
+        .crtcs
+        .iter()
+        .map(|crtc| conn.randr_get_crtc_info(*crtc, resources.config_timestamp))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut monitors: Vec<(String, Rect)> = vec![];
+    for cookie in cookies {
+        let crtc = cookie.reply()?;
+        // disabled CRTCs have no outputs and no size
+        let output: u32 = match crtc.outputs.first() {
+            Some(output) if crtc.width > 0 && crtc.height > 0 => *output,
+            _ => continue,
+        };
+        let r: Rect = Rect::new(
+            crtc.x as i32,
+            crtc.y as i32,
+            crtc.width as u32,
+            crtc.height as u32,
+        );
+        if monitors.iter().any(|(_, other)| *other == r) {
+            continue;
+        }
+        let info = conn
+            .randr_get_output_info(output, resources.config_timestamp)?
+            .reply()?;
+        monitors.push((String::from_utf8_lossy(&info.name).into_owned(), r));
+    }
+    Ok(monitors)
+}
+
+// Gives every new monitor a workspace. Monitors which are still there keep the workspace
+// they show, new ones get one of the monitors which are gone, or else the first hidden
+// workspace. Monitors beyond the number of workspaces stay unused.
+pub fn assign_workspaces(
+    old: &[Monitor],
+    new: Vec<(String, Rect)>,
+    workspaces: usize,
+) -> Vec<Monitor> {
+    let mut new: Vec<(String, Rect)> = new;
+    if new.len() > workspaces {
+        warn!(
+            "{} monitors but only {} workspaces, leaving some monitors empty",
+            new.len(),
+            workspaces
+        );
+        new.truncate(workspaces);
+    }
+    let mut assigned: Vec<Option<usize>> = new
+        .iter()
+        .map(|(name, _)| old.iter().find(|m| m.name == *name).map(|m| m.workspace))
+        .collect();
+    let gone: Vec<usize> = old
+        .iter()
+        .map(|m| m.workspace)
+        .filter(|ws| !assigned.contains(&Some(*ws)))
+        .collect();
+    let mut gone = gone.into_iter();
+    for workspace in assigned.iter_mut().filter(|ws| ws.is_none()) {
+        *workspace = gone.next();
+    }
+    for i in 0..assigned.len() {
+        if assigned[i].is_none() {
+            assigned[i] = (0..workspaces).find(|ws| !assigned.contains(&Some(*ws)));
+        }
+    }
+
+    new.into_iter()
+        .zip(assigned)
+        .map(|((name, rect), workspace)| Monitor {
+            name,
+            rect,
+            workspace: workspace.unwrap_or_default(),
+        })
+        .collect()
+}
+
+// Where a floating window at r on monitor from goes when its workspace is shown on
+// monitor to. It keeps its position relative to the monitor, unless that would leave it
+// outside of the new monitor, in which case it is centred on it.
+pub fn move_between(r: Rect, from: Rect, to: Rect) -> Rect {
+    let moved: Rect = Rect::new(r.x - from.x + to.x, r.y - from.y + to.y, r.w, r.h);
+    let visible: bool = moved.x < to.x + to.w as i32
+        && moved.x + moved.w as i32 > to.x
+        && moved.y < to.y + to.h as i32
+        && moved.y + moved.h as i32 > to.y;
+    if visible {
+        return moved;
+    }
+    r.centered_in(to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(name: &str, x: i32, workspace: usize) -> Monitor {
+        Monitor {
+            name: name.to_string(),
+            rect: Rect::new(x, 0, 1920, 1080),
+            workspace,
+        }
+    }
+
+    fn output(name: &str, x: i32) -> (String, Rect) {
+        (name.to_string(), Rect::new(x, 0, 1920, 1080))
+    }
+
+    #[test]
+    fn first_monitors_get_first_workspaces() {
+        let monitors: Vec<Monitor> =
+            assign_workspaces(&[], vec![output("DP-1", 0), output("HDMI-1", 1920)], 9);
+        assert_eq!(
+            monitors,
+            vec![monitor("DP-1", 0, 0), monitor("HDMI-1", 1920, 1)]
+        );
+    }
+
+    #[test]
+    fn monitors_keep_their_workspace() {
+        let old: Vec<Monitor> = vec![monitor("DP-1", 0, 4), monitor("HDMI-1", 1920, 2)];
+        let monitors: Vec<Monitor> =
+            assign_workspaces(&old, vec![output("HDMI-1", 0), output("DP-1", 1920)], 9);
+        assert_eq!(
+            monitors,
+            vec![monitor("HDMI-1", 0, 2), monitor("DP-1", 1920, 4)]
+        );
+    }
+
+    #[test]
+    fn new_monitors_take_over_workspaces_of_gone_ones() {
+        let old: Vec<Monitor> = vec![monitor("DP-1", 0, 0), monitor("HDMI-1", 1920, 3)];
+        let monitors: Vec<Monitor> =
+            assign_workspaces(&old, vec![output("DP-1", 0), output("DP-2", 1920)], 9);
+        assert_eq!(
+            monitors,
+            vec![monitor("DP-1", 0, 0), monitor("DP-2", 1920, 3)]
+        );
+    }
+
+    #[test]
+    fn added_monitors_get_the_first_hidden_workspace() {
+        let old: Vec<Monitor> = vec![monitor("DP-1", 0, 0)];
+        let monitors: Vec<Monitor> =
+            assign_workspaces(&old, vec![output("DP-1", 0), output("DP-2", 1920)], 9);
+        assert_eq!(monitors[1].workspace, 1);
+    }
+
+    #[test]
+    fn monitors_without_workspace_are_unused() {
+        let monitors: Vec<Monitor> =
+            assign_workspaces(&[], vec![output("DP-1", 0), output("DP-2", 1920)], 1);
+        assert_eq!(monitors, vec![monitor("DP-1", 0, 0)]);
+    }
+
+    #[test]
+    fn floating_windows_move_along_with_their_workspace() {
+        let from: Rect = Rect::new(0, 0, 1920, 1080);
+        let to: Rect = Rect::new(1920, 0, 1280, 720);
+        assert_eq!(
+            move_between(Rect::new(100, 100, 400, 300), from, to),
+            Rect::new(2020, 100, 400, 300)
+        );
+        // it would end up below the smaller monitor
+        assert_eq!(
+            move_between(Rect::new(100, 800, 400, 300), from, to),
+            Rect::new(2360, 210, 400, 300)
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use crate::layout::{Layout, Rect};
+use x11rb::protocol::xproto::Window;
+
+// A named group of clients, every monitor shows one workspace.
+// Workspaces are created from Config.workspaces, like the tags of the penrose backend.
+pub struct Workspace {
+    pub name: String,
+    // Clients in tiling order, the first ones make up the main area.
+    pub stack: Vec<Window>,
+    // The client which had focus when the workspace was left.
+    pub focused: Option<Window>,
+    // The monitor area the workspace was last shown on, floating clients are positioned
+    // relative to it.
+    pub area: Option<Rect>,
+    // Layouts of the workspace, each with main area settings of its own.
+    pub layouts: Vec<Layout>,
+    // Index of the active layout in layouts.
+    pub layout: usize,
+}
+
+impl Workspace {
+    pub fn new(name: &str, layouts: Vec<Layout>) -> Self {
+        Workspace {
+            name: name.to_string(),
+            stack: vec![],
+            focused: None,
+            area: None,
+            layouts,
+            layout: 0,
+        }
+    }
+
+    pub fn layout(&self) -> &Layout {
+        &self.layouts[self.layout]
+    }
+
+    pub fn contains(&self, w: Window) -> bool {
+        self.stack.contains(&w)
+    }
+
+    pub fn remove(&mut self, w: Window) {
+        self.stack.retain(|c| *c != w);
+        if self.focused == Some(w) {
+            self.focused = self.stack.first().copied();
+        }
+    }
+}
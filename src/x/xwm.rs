@@ -1,229 +1,2349 @@
-use std::cell::RefCell;
-use env_logger;
-use gtk::atk::Window;
-use libc::{c_int, c_uchar, c_ulong};
+use crate::bar::{Bar, Status, WorkspaceStatus};
+use crate::config::{Config, FocusModel};
+use crate::hints::{get_client_hints, ClientHints, InputModel};
+use crate::ipc::protocol::{self, Command, Event as IpcEvent, Reply, Tree};
+use crate::ipc::server::{self, Events, Request};
+use crate::layout::{Gaps, Layout, Rect, Strut};
+use crate::restart::{self, SavedLayout, SavedState, SavedWorkspace};
+use crate::rules::{Effects, Rules, WindowProps};
+use crate::util::{self, force_kill_after};
+use crate::x::cursor::Cursors;
+use crate::x::error::XwmError;
+use crate::x::ewmh;
+use crate::x::keys::{key_actions, keycodes_for, parse_bind, KeyAction, KeyBind};
+use crate::x::monitor::{self, Monitor};
+use crate::x::workspace::Workspace;
 use log::{error, info, warn};
-use penrose::pure::Position;
-use std::collections::HashMap;
-use std::ffi::CString;
-use std::ptr;
-use std::ptr::null;
+use penrose::util::spawn;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::path::PathBuf;
 use std::rc::Rc;
-use gtk::atk::RelationType::Null;
-use x11::keysym::XK_F4;
-use x11::xlib::{self, XConfigureEvent, XConfigureRequestEvent, XCreateWindowEvent, XDestroyWindowEvent, XDisplayName, XGetGeometry, XMapEvent, XMapRequestEvent, XOpenDisplay, XReparentEvent, XUnmapEvent};
-use x11::xlib::{
-    BadAccess, Display, Mod1Mask, MotionNotify, SubstructureNotifyMask, SubstructureRedirectMask,
-    XButtonEvent, XCheckTypedWindowEvent, XDisplayString, XErrorEvent, XEvent, XGetErrorText,
-    XKeyEvent, XKeysymToKeycode, XMotionEvent, XNextEvent, XSelectInput, XSetErrorHandler, XSync,
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+use x11rb::connection::Connection;
+use x11rb::errors::ReplyError;
+use x11rb::properties::WmClass;
+use x11rb::protocol::xproto::{
+    Allow, Atom, AtomEnum, ButtonIndex, ButtonPressEvent, ButtonReleaseEvent,
+    ChangeWindowAttributesAux, ClientMessageEvent, ConfigWindow, ConfigureNotifyEvent,
+    ConfigureRequestEvent, ConfigureWindowAux, ConnectionExt, CreateNotifyEvent, CreateWindowAux,
+    DestroyNotifyEvent, EnterNotifyEvent, EventMask, Grab, GrabMode, InputFocus, KeyButMask,
+    KeyPressEvent, KeyReleaseEvent, Keycode, MapNotifyEvent, MapRequestEvent, MapState, Mapping,
+    MappingNotifyEvent, ModMask, MotionNotifyEvent, NotifyMode, PropertyNotifyEvent,
+    ReparentNotifyEvent, SetMode, StackMode, UnmapNotifyEvent, Window, WindowClass,
+    CONFIGURE_NOTIFY_EVENT,
 };
+use x11rb::protocol::{ErrorKind, Event};
+use x11rb::rust_connection::RustConnection;
+use x11rb::{CURRENT_TIME, NONE};
+
+x11rb::atom_manager! {
+    // Atoms used by Xwm, interned all at once when connecting.
+    pub Atoms: AtomsCookie {
+        WM_PROTOCOLS,
+        WM_DELETE_WINDOW,
+        WM_TAKE_FOCUS,
+        WM_WINDOW_ROLE,
+        UTF8_STRING,
+        _NET_SUPPORTED,
+        _NET_SUPPORTING_WM_CHECK,
+        _NET_WM_NAME,
+        _NET_CLIENT_LIST,
+        _NET_CLIENT_LIST_STACKING,
+        _NET_ACTIVE_WINDOW,
+        _NET_NUMBER_OF_DESKTOPS,
+        _NET_DESKTOP_NAMES,
+        _NET_CURRENT_DESKTOP,
+        _NET_CLOSE_WINDOW,
+        _NET_WM_DESKTOP,
+        _NET_WM_PID,
+        _NET_WM_WINDOW_TYPE,
+        _NET_WM_WINDOW_TYPE_DOCK,
+        _NET_WM_WINDOW_TYPE_DIALOG,
+        _NET_WM_WINDOW_TYPE_UTILITY,
+        _NET_WM_WINDOW_TYPE_SPLASH,
+        _NET_WM_STRUT,
+        _NET_WM_STRUT_PARTIAL,
+        _HIPPOWM_SHUTDOWN,
+        _HIPPOWM_IPC,
+    }
+}
+
 pub struct Xwm {
-    // Handle to the underlying Xlib Display struct.
-    display: *mut xlib::Display,
+    // Connection to the X server.
+    conn: RustConnection,
+    // Name of the display we are connected to, None if $DISPLAY was used.
+    display_name: Option<String>,
     // Handle to root window.
-    root: xlib::Window,
-    clients: HashMap<u64, u64>,
+    root: Window,
+    atoms: Atoms,
+    // Maps top-level client windows to the frame windows they were reparented into.
+    clients: HashMap<Window, Window>,
+    // The client window which currently has input focus, if any.
+    focused: Option<Window>,
+    // Number of UnmapNotify events per client which were caused by our own reparenting
+    // and therefore have to be ignored (ICCCM 4.1.4).
+    ignore_unmaps: HashMap<Window, u32>,
+    // Cursor position on the root window when the current drag started.
+    drag_start_pos: (i32, i32),
+    // Position and size of the dragged frame when the current drag started.
+    drag_start_frame_pos: (i32, i32),
+    drag_start_frame_size: (u32, u32),
+    workspaces: Vec<Workspace>,
+    // Index of the workspace on the focused monitor and of the one shown there before it.
+    current: usize,
+    previous: usize,
+    // Active monitors, each of them shows its own workspace.
+    monitors: Vec<Monitor>,
+    // Index of the focused monitor, the one showing the current workspace.
+    monitor: usize,
+    // Area of the root window, which spans all monitors.
+    root_rect: Rect,
+    // The window _NET_SUPPORTING_WM_CHECK points to, NONE until we manage the screen.
+    check_window: Window,
+    // Cleared when a shutdown signal arrived, which ends the event loop.
+    running: bool,
+    // File the state was saved to by the restart action, main execs us again once we
+    // are done.
+    restart: Option<PathBuf>,
+    // State saved before the restart we started from, while the existing clients are framed.
+    restoring: Option<SavedState>,
+    // Commands of hippowmc and other clients of the socket, None if it could not be opened.
+    requests: Option<Receiver<Request>>,
+    // Subscribers of the socket, which are told about changes.
+    events: Events,
+    // The bar of Config.bar, None if it is disabled or could not be created.
+    bar: Option<Bar>,
+    // Clients which are not tiled and keep the geometry they ask for.
+    floating: HashSet<Window>,
+    // ICCCM hints of every client, kept up to date on PropertyNotify.
+    hints: HashMap<Window, ClientHints>,
+    // Transient clients, e.g. dialogs, and the client they belong to. They float above
+    // their parent and follow it to other workspaces.
+    transients: HashMap<Window, Window>,
+    // Clients whose frame has no border, see Config.rules.
+    borderless: HashSet<Window>,
+    // Mapped dock windows, e.g. bars, which are not framed and the space they reserve.
+    docks: HashMap<Window, Strut>,
+    gaps: Gaps,
+    // The fullscreen client and the geometry its frame had before.
+    fullscreen: Option<(Window, Rect)>,
+    // Grabbed key combinations by modifier mask and keycode.
+    keys: HashMap<(u16, Keycode), KeyAction>,
+    // Modifier mask of NumLock, which is ignored together with CapsLock when matching keys.
+    numlock_mask: u16,
+    // Pointer position after the layout was last applied, EnterNotify events at this
+    // position were caused by the layout and not the pointer, which sloppy focus ignores.
+    layout_pointer: Cell<Option<(i16, i16)>>,
+    // Cursors of the cursor theme, shown on root and while dragging.
+    cursors: Cursors,
+    rules: Rules,
+    config: Config,
 }
 
-//modifying this value could cause undefined behavior, which
-//is why unsafe {} blocks are necessary, when assigning a new value
-static mut WM_DETECTED: bool = false;
+// Width of the border drawn around every frame.
+const BORDER_WIDTH: u32 = 2;
+// Modifier which has to be held to move or resize windows with the mouse.
+const MOD_MASK: ModMask = ModMask::M4;
+// Windows can not be resized below this size using the mouse.
+const MIN_WIDTH: u32 = 50;
+const MIN_HEIGHT: u32 = 50;
 
 impl Xwm {
     //This method connects to the X server and creates a wm instance
-    pub fn create(display_string : &str) -> Result<Rc<RefCell<Xwm>>, String> {
-        let display_c_string : CString = CString::new(display_string).unwrap_or_default();
-        let display : *mut Display;
-        unsafe {
-            display = XOpenDisplay(display_c_string.as_ptr());
-        }
-        if display.is_null() {
-            //return Err(format!("Could not open XDisplay: {}", unsafe {XDisplayName(display_c_string.as_ptr())}));
-            return Err("Could not open XDisplay".parse().unwrap());
-
-        }
-        let window_manager : Rc<RefCell<Xwm>> = Rc::new(RefCell::new(Self::xwm(display)));
+    pub fn create(display_string: &str, config: Config) -> Result<Rc<RefCell<Xwm>>, XwmError> {
+        let display_name: Option<String> = if display_string.is_empty() {
+            None
+        } else {
+            Some(display_string.to_string())
+        };
+        let (conn, screen_num) = match x11rb::connect(display_name.as_deref()) {
+            Ok(connection) => connection,
+            Err(reason) => {
+                // without a name x11rb tried $DISPLAY
+                let display: String = display_name
+                    .clone()
+                    .or_else(|| env::var("DISPLAY").ok())
+                    .unwrap_or_default();
+                return Err(XwmError::DisplayOpen { display, reason });
+            }
+        };
+        // the atoms are interned in one round trip, before anything else happens
+        let atoms: Atoms = Atoms::new(&conn)?.reply()?;
+        let window_manager: Rc<RefCell<Xwm>> = Rc::new(RefCell::new(Self::xwm(
+            conn,
+            screen_num,
+            atoms,
+            display_name,
+            config,
+        )));
         Ok(window_manager)
     }
 
     // Invoked internally by create().
-    fn xwm(display: *mut xlib::Display) -> Self {
-        let clients: HashMap<u64, u64> = HashMap::new();
+    fn xwm(
+        conn: RustConnection,
+        screen_num: usize,
+        atoms: Atoms,
+        display_name: Option<String>,
+        config: Config,
+    ) -> Self {
+        let clients: HashMap<Window, Window> = HashMap::new();
+        let screen = &conn.setup().roots[screen_num];
+        let root: Window = screen.root;
+        let root_rect: Rect = Rect::new(
+            0,
+            0,
+            screen.width_in_pixels as u32,
+            screen.height_in_pixels as u32,
+        );
+        let mut workspaces: Vec<Workspace> = config
+            .workspaces
+            .iter()
+            .map(|name| Workspace::new(name, Layout::all(&config)))
+            .collect();
+        // there has to be somewhere to put clients
+        if workspaces.is_empty() {
+            workspaces.push(Workspace::new("1", Layout::all(&config)));
+        }
         return Xwm {
-            display,
-            root: unsafe { xlib::XDefaultRootWindow(display) },
+            conn,
+            display_name,
+            root,
+            atoms,
             clients,
+            focused: None,
+            ignore_unmaps: HashMap::new(),
+            drag_start_pos: (0, 0),
+            drag_start_frame_pos: (0, 0),
+            drag_start_frame_size: (0, 0),
+            workspaces,
+            current: 0,
+            previous: 0,
+            // replaced by the monitors RandR knows once we run
+            monitors: vec![Monitor {
+                name: "root".to_string(),
+                rect: root_rect,
+                workspace: 0,
+            }],
+            monitor: 0,
+            root_rect,
+            check_window: NONE,
+            running: true,
+            restart: None,
+            restoring: None,
+            requests: None,
+            events: Events::default(),
+            bar: None,
+            floating: HashSet::new(),
+            hints: HashMap::new(),
+            transients: HashMap::new(),
+            borderless: HashSet::new(),
+            docks: HashMap::new(),
+            gaps: Gaps::from_config(&config),
+            fullscreen: None,
+            keys: HashMap::new(),
+            numlock_mask: 0,
+            layout_pointer: Cell::new(None),
+            // loaded once we run, NONE is the cursor of the parent window
+            cursors: Cursors {
+                normal: NONE,
+                moving: NONE,
+                resizing: NONE,
+            },
+            rules: Rules::new(&config.rules),
+            config,
         };
     }
 
-    // Disconnects from the X server.
-    pub fn close(&self) {
-        unsafe {
-            xlib::XCloseDisplay(self.display);
+    // Gives every client back to root at its current position, removes the EWMH
+    // properties and sends what is still buffered. The connection itself is closed when
+    // Xwm is dropped. Errors are ignored, as there is nothing left to do about them.
+    pub fn close(&mut self) {
+        // another wm is running or we never got that far, so there is nothing to undo
+        if self.check_window == NONE {
+            return;
+        }
+        info!("Restoring {} clients", self.clients.len());
+        for (w, frame) in self.clients.drain() {
+            let (x, y): (i16, i16) = match self.conn.get_geometry(frame).map(|c| c.reply()) {
+                Ok(Ok(geometry)) => (
+                    geometry.x + geometry.border_width as i16,
+                    geometry.y + geometry.border_width as i16,
+                ),
+                _ => (0, 0),
+            };
+            let _ = self.conn.reparent_window(w, self.root, x, y);
+            let _ = self.conn.change_save_set(SetMode::DELETE, w);
+            let _ = self.conn.destroy_window(frame);
+        }
+        for property in [
+            self.atoms._NET_SUPPORTED,
+            self.atoms._NET_SUPPORTING_WM_CHECK,
+            self.atoms._NET_CLIENT_LIST,
+            self.atoms._NET_CLIENT_LIST_STACKING,
+            self.atoms._NET_ACTIVE_WINDOW,
+            self.atoms._NET_NUMBER_OF_DESKTOPS,
+            self.atoms._NET_DESKTOP_NAMES,
+            self.atoms._NET_CURRENT_DESKTOP,
+        ] {
+            let _ = self.conn.delete_property(self.root, property);
         }
+        let _ = self.conn.destroy_window(self.check_window);
+        self.check_window = NONE;
+        let _ = self.conn.set_input_focus(
+            InputFocus::POINTER_ROOT,
+            InputFocus::POINTER_ROOT,
+            CURRENT_TIME,
+        );
+        let _ = self.conn.flush();
+    }
+
+    // The file the restart action saved the state to, once run returned because of it.
+    pub fn restart_file(&self) -> Option<PathBuf> {
+        self.restart.clone()
     }
 
     // The entry point to this class. Enters the main event loop.
-    pub fn run(&self) {
-        //We begin by selecting the events on the root window and by
-        //using a special error handler we can exit if another wm is running.
-        let error_handler_init: Option<
-            unsafe extern "C" fn(*mut Display, *mut XErrorEvent) -> c_int,
-        > = Some(on_detected_wm);
-
-        unsafe {
-            WM_DETECTED = false;
-            XSetErrorHandler(error_handler_init);
-            XSelectInput(
-                self.display,
-                self.root,
-                SubstructureNotifyMask | SubstructureRedirectMask,
-            );
-            XSync(self.display, 0);
-            if (WM_DETECTED) {
-                error!(
-                    //"Error, other wm on display {}",
-                    //XDisplayString(self.display)
-                    "This is another wm on display"
-                );
-                return;
+    pub fn run(&mut self) -> Result<(), XwmError> {
+        //We begin by selecting the events on the root window. Only one client
+        //can select SubstructureRedirect, so failing with BadAccess means
+        //another wm is running.
+        let root_events = ChangeWindowAttributesAux::new()
+            .event_mask(EventMask::SUBSTRUCTURE_NOTIFY | EventMask::SUBSTRUCTURE_REDIRECT);
+        match self
+            .conn
+            .change_window_attributes(self.root, &root_events)?
+            .check()
+        {
+            Ok(()) => {}
+            Err(ReplyError::X11Error(error)) if error.error_kind == ErrorKind::Access => {
+                let display: String = self
+                    .display_name
+                    .clone()
+                    .or_else(|| env::var("DISPLAY").ok())
+                    .unwrap_or_default();
+                return Err(XwmError::WmDetected(display));
             }
+            Err(error) => return Err(error.into()),
         }
-        let error_handler: Option<unsafe extern "C" fn(*mut Display, *mut XErrorEvent) -> c_int> =
-            Some(on_x_error);
 
-        //now that we now that no other wm is running, we can use a different
-        //error handler.
-        unsafe {
-            XSetErrorHandler(error_handler);
+        // without a cursor of its own root shows the X shaped default cursor
+        match Cursors::load(&self.conn, self.root, &self.config) {
+            Ok(cursors) => {
+                self.cursors = cursors;
+                self.conn.change_window_attributes(
+                    self.root,
+                    &ChangeWindowAttributesAux::new().cursor(cursors.normal),
+                )?;
+            }
+            Err(error) => warn!("Could not load cursors: {}", error),
         }
 
-        loop {
-            let mut event: XEvent = unsafe {std::mem::zeroed()};
-            unsafe { XNextEvent(self.display, &mut event) };
-            info!("Received event: {:?}", event);
+        self.check_window = ewmh::announce(&self.conn, self.root, &self.atoms)?;
+        let names: Vec<String> = self.workspaces.iter().map(|ws| ws.name.clone()).collect();
+        ewmh::set_desktops(&self.conn, self.root, &self.atoms, &names)?;
+        ewmh::set_current_desktop(&self.conn, self.root, &self.atoms, self.current)?;
+        monitor::select_changes(&self.conn, self.root)?;
+        self.update_monitors()?;
+        let saved: Option<SavedState> = restart::take();
+        if let Some(saved) = &saved {
+            self.restore_screens(saved)?;
+        }
 
-            unsafe {
-                match (event.type_) {
-                    xlib::KeyPress => Self::on_key_press(&self, event.key),
-                    xlib::KeyRelease => Self::on_key_release(&self, event.key),
-                    xlib::ButtonPress => Self::on_button_press(&self, event.button),
-                    xlib::ButtonRelease => Self::on_button_release(&self, event.button),
-                    xlib::MotionNotify => {
-                        while XCheckTypedWindowEvent(
-                            self.display,
-                            event.motion.window,
-                            MotionNotify,
-                            &mut event,
-                        ) != 0 {}
-                        Self::on_motion_notify(&self, event.motion);
-                    }
-                    xlib::CreateNotify => Self::on_create_notify(&self, event.create_window),
-                    xlib::DestroyNotify => Self::on_destroy_notify(&self, event.destroy_window),
-                    xlib::ReparentNotify => Self::on_reparant_notify(&self, event.reparent),
-                    xlib::MapNotify => Self::on_map_notify(&self, event.map),
-                    xlib::UnmapNotify => Self::on_unmap_notify(&self, event.unmap),
-                    xlib::ConfigureNotify => Self::on_configure_notify(&self, event.configure),
-                    xlib::MapRequest => Self::on_map_request(&self, event.map_request),
-                    xlib::ConfigureRequest => {
-                        Self::on_configure_request(&self, event.configure_request)
+        self.grab_keys()?;
+        self.restoring = saved;
+        self.adopt_existing_windows()?;
+        if let Some(saved) = self.restoring.take() {
+            self.restore(saved)?;
+        }
+        if let Err(error) = util::notify_on_signals(self.display_name.clone()) {
+            warn!("Could not handle shutdown signals: {}", error);
+        }
+        let (sender, requests) = mpsc::channel();
+        // the check window is unmapped and ours, so it receives IPC_MESSAGE
+        let listening = server::listen(
+            self.display_name.clone(),
+            self.check_window,
+            sender,
+            self.events.clone(),
+        );
+        match listening {
+            Ok(()) => self.requests = Some(requests),
+            Err(error) => warn!("Could not listen for commands: {}", error),
+        }
+        // created once we manage the screen, so it is mapped like every other dock
+        if self.config.bar {
+            match Bar::spawn(self.display_name.as_deref(), &self.config) {
+                Ok(bar) => self.bar = Some(bar),
+                Err(error) => warn!("Could not create the bar: {}", error),
+            }
+        }
+        if let Err(error) = self.update_bar() {
+            error!("{}", error);
+        }
+
+        // an event which was taken from the queue while compressing motion events
+        let mut pending: Option<Event> = None;
+        while self.running {
+            self.conn.flush()?;
+            let mut event: Event = match pending.take() {
+                Some(event) => event,
+                None => self.conn.wait_for_event()?,
+            };
+
+            // only the latest motion of a drag matters, so queued ones are skipped
+            if let Event::MotionNotify(motion) = &event {
+                let window: Window = motion.event;
+                while let Some(next) = self.conn.poll_for_event()? {
+                    match next {
+                        Event::MotionNotify(ref m) if m.event == window => event = next,
+                        other => {
+                            pending = Some(other);
+                            break;
+                        }
                     }
-                    _ => warn!("Ignored event"),
+                }
+            }
+            info!("Received event: {:?}", event);
+
+            // motion only moves windows around, which the bar does not show
+            let motion: bool = matches!(event, Event::MotionNotify(_));
+            if let Err(error) = self.handle_event(event) {
+                if error.is_fatal() {
+                    return Err(error);
+                }
+                error!("{}", error);
+            }
+            if !motion {
+                if let Err(error) = self.update_bar() {
+                    error!("{}", error);
                 }
             }
         }
+        info!("Shutting down");
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: Event) -> Result<(), XwmError> {
+        match event {
+            Event::KeyPress(e) => self.on_key_press(e),
+            Event::KeyRelease(e) => self.on_key_release(e),
+            Event::ButtonPress(e) => self.on_button_press(e),
+            Event::ButtonRelease(e) => self.on_button_release(e),
+            Event::MotionNotify(e) => self.on_motion_notify(e),
+            Event::EnterNotify(e) => self.on_enter_notify(e),
+            Event::CreateNotify(e) => self.on_create_notify(e),
+            Event::DestroyNotify(e) => self.on_destroy_notify(e),
+            Event::ReparentNotify(e) => self.on_reparant_notify(e),
+            Event::MapNotify(e) => self.on_map_notify(e),
+            Event::PropertyNotify(e) => self.on_property_notify(e),
+            Event::UnmapNotify(e) => self.on_unmap_notify(e),
+            Event::ConfigureNotify(e) => self.on_configure_notify(e),
+            Event::MapRequest(e) => self.on_map_request(e),
+            Event::MappingNotify(e) => self.on_mapping_notify(e),
+            Event::ConfigureRequest(e) => self.on_configure_request(e),
+            Event::ClientMessage(e) => self.on_client_message(e),
+            // outputs were connected, disconnected or changed their resolution
+            Event::RandrScreenChangeNotify(_) => self.update_monitors(),
+            // errors of requests whose cookies were not checked end up here
+            Event::Error(e) => Err(e.into()),
+            _ => {
+                warn!("Ignored event");
+                Ok(())
+            }
+        }
     }
 
     // Key Events
-    fn on_key_press(&self, e: XKeyEvent) {
-        unsafe {
-            //  check for alt+f4
-            if e.keycode == XKeysymToKeycode(self.display, XK_F4 as u64) as u32
-                && (e.state & Mod1Mask as u32) != 0
-            { //TODO implement this
+    fn on_key_press(&mut self, e: KeyPressEvent) -> Result<(), XwmError> {
+        // CapsLock and NumLock must not change the meaning of a binding
+        let relevant: u16 = u16::from(
+            ModMask::SHIFT
+                | ModMask::CONTROL
+                | ModMask::M1
+                | ModMask::M2
+                | ModMask::M3
+                | ModMask::M4
+                | ModMask::M5,
+        );
+        let mask: u16 =
+            u16::from(e.state) & relevant & !(u16::from(ModMask::LOCK) | self.numlock_mask);
+        match self.keys.get(&(mask, e.detail)).cloned() {
+            Some(KeyAction::Action(action)) => {
+                self.action(&action)?;
+            }
+            Some(KeyAction::Spawn(command)) => {
+                if let Err(error) = spawn(command.as_str()) {
+                    warn!("Could not spawn {}: {}", command, error);
+                }
+            }
+            Some(KeyAction::Workspace(name)) => {
+                if let Some(index) = self.workspace_index(&name) {
+                    self.switch_workspace(index)?;
+                }
             }
+            Some(KeyAction::MoveToWorkspace(name)) => {
+                if let Some(index) = self.workspace_index(&name) {
+                    self.move_focused_to_workspace(index)?;
+                }
+            }
+            None => {}
         }
+        Ok(())
+    }
+    fn on_key_release(&self, _e: KeyReleaseEvent) -> Result<(), XwmError> {
+        Ok(())
     }
-    fn on_key_release(&self, e: XKeyEvent) {}
 
     // Button Events
-    fn on_button_press(&self, e: XButtonEvent) {
-        let window: u64 = e.window;
-        assert!(self.clients.contains_key(&window));
-        let frame: u64 = self.clients[&window];
+    fn on_button_press(&mut self, e: ButtonPressEvent) -> Result<(), XwmError> {
+        let window: Window = e.event;
+        if u16::from(e.state) & u16::from(MOD_MASK) == 0 {
+            return self.on_click(window);
+        }
+        let frame: Window = match self.clients.get(&window) {
+            Some(frame) => *frame,
+            None => {
+                warn!("Button press on unmanaged window {}", window);
+                // the press may come from the click to focus grab, which froze the pointer
+                self.conn
+                    .allow_events(Allow::REPLAY_POINTER, CURRENT_TIME)?;
+                return Ok(());
+            }
+        };
+        // with NumLock or CapsLock on, a drag may come from the click to focus grab as well,
+        // the press is kept and the pointer thawed
+        self.conn.allow_events(Allow::ASYNC_POINTER, CURRENT_TIME)?;
 
         // 1. Save initial cursor position.
-        let drag_start_pos = (e.x_root, e.y_root);
+        self.drag_start_pos = (e.root_x as i32, e.root_y as i32);
 
         // 2. Save initial window info.
-        let mut returned_root: u64 = 0;
-        let mut x = 0;
-        let mut y = 0;
-        let mut width = 0;
-        let mut height = 0;
-        let mut border_width = 0;
-        let mut depth = 0;
-        unsafe {
-            assert_eq!(
-                XGetGeometry(
-                    self.display,
-                    frame,
-                    &mut returned_root,
-                    &mut x,
-                    &mut y,
-                    &mut width,
-                    &mut height,
-                    &mut border_width,
-                    &mut depth,
-                ),
-                1
-            );
+        let geometry = self.conn.get_geometry(frame)?.reply()?;
+        self.drag_start_frame_pos = (geometry.x as i32, geometry.y as i32);
+        self.drag_start_frame_size = (geometry.width as u32, geometry.height as u32);
+
+        // 3. Raise clicked window to top. Dragged windows leave the layout.
+        self.raise(window)?;
+        if self.floating.insert(window) {
+            self.apply_layout()?;
         }
+        self.focus(window)
+    }
+    // A click without MOD_MASK, grabbed for click to focus. The pointer is frozen until
+    // the click is replayed to the client.
+    fn on_click(&mut self, w: Window) -> Result<(), XwmError> {
+        let focused: Result<(), XwmError> = self.click_focus(w);
+        // replayed even if focusing failed, the pointer would stay frozen otherwise
+        self.conn
+            .allow_events(Allow::REPLAY_POINTER, CURRENT_TIME)?;
+        focused
+    }
+
+    fn click_focus(&mut self, w: Window) -> Result<(), XwmError> {
+        if self.clients.contains_key(&w) && self.focused != Some(w) {
+            if self.floating.contains(&w) {
+                self.raise(w)?;
+            }
+            self.focus(w)?;
+        }
+        Ok(())
+    }
+
+    fn on_button_release(&self, _e: ButtonReleaseEvent) -> Result<(), XwmError> {
+        Ok(())
     }
-    fn on_button_release(&self, e: XButtonEvent) {}
 
     // Notification Events
-    fn on_motion_notify(&self, e: XMotionEvent) {}
-    fn on_create_notify(&self, e: XCreateWindowEvent) {}
-    fn on_destroy_notify(&self, e: XDestroyWindowEvent) {}
-    fn on_reparant_notify(&self, e: XReparentEvent) {}
-    fn on_map_notify(&self, e: XMapEvent) {}
-    fn on_unmap_notify(&self, e: XUnmapEvent) {}
-    fn on_configure_notify(&self, e: XConfigureEvent) {}
+    fn on_motion_notify(&self, e: MotionNotifyEvent) -> Result<(), XwmError> {
+        let frame: Window = match self.clients.get(&e.event) {
+            Some(frame) => *frame,
+            None => return Ok(()),
+        };
+        let delta_x: i32 = e.root_x as i32 - self.drag_start_pos.0;
+        let delta_y: i32 = e.root_y as i32 - self.drag_start_pos.1;
+
+        if e.state.contains(KeyButMask::BUTTON1) {
+            // mod + left button: move the frame
+            let x: i32 = self.drag_start_frame_pos.0 + delta_x;
+            let y: i32 = self.drag_start_frame_pos.1 + delta_y;
+            self.conn
+                .configure_window(frame, &ConfigureWindowAux::new().x(x).y(y))?;
+        } else if e.state.contains(KeyButMask::BUTTON3) {
+            // mod + right button: resize frame and client within the size hints
+            let width: u32 =
+                (self.drag_start_frame_size.0 as i32 + delta_x).max(MIN_WIDTH as i32) as u32;
+            let height: u32 =
+                (self.drag_start_frame_size.1 as i32 + delta_y).max(MIN_HEIGHT as i32) as u32;
+            let (width, height) = self.client_hints(e.event).size.constrain(width, height);
+            let size = ConfigureWindowAux::new().width(width).height(height);
+            self.conn.configure_window(frame, &size)?;
+            self.conn.configure_window(e.event, &size)?;
+        }
+        Ok(())
+    }
+    // The pointer entered a frame, which focuses its client unless focus is by click.
+    fn on_enter_notify(&mut self, e: EnterNotifyEvent) -> Result<(), XwmError> {
+        // crossings caused by grabs, e.g. while dragging, are no movement of the pointer
+        if e.mode != NotifyMode::NORMAL || self.config.focus == FocusModel::Click {
+            return Ok(());
+        }
+        if self.config.focus == FocusModel::Sloppy
+            && self.layout_pointer.get() == Some((e.root_x, e.root_y))
+        {
+            return Ok(());
+        }
+        let w: Window = match self.clients.iter().find(|(_, frame)| **frame == e.event) {
+            Some((w, _)) => *w,
+            None => return Ok(()),
+        };
+        if self.focused != Some(w) {
+            self.focus(w)?;
+        }
+        Ok(())
+    }
+
+    fn on_create_notify(&self, _e: CreateNotifyEvent) -> Result<(), XwmError> {
+        Ok(())
+    }
+    fn on_destroy_notify(&mut self, e: DestroyNotifyEvent) -> Result<(), XwmError> {
+        self.ignore_unmaps.remove(&e.window);
+        if self.docks.remove(&e.window).is_some() {
+            return self.apply_layout();
+        }
+        if let Some(frame) = self.clients.remove(&e.window) {
+            // the client vanished without being unmapped first, so the frame is still around
+            self.conn.destroy_window(frame)?;
+            info!("Client {} destroyed, removed frame {}", e.window, frame);
+            self.forget(e.window)?;
+            self.apply_layout()?;
+            self.update_client_list()?;
+        }
+        Ok(())
+    }
+    fn on_reparant_notify(&self, _e: ReparentNotifyEvent) -> Result<(), XwmError> {
+        Ok(())
+    }
+    fn on_map_notify(&self, _e: MapNotifyEvent) -> Result<(), XwmError> {
+        Ok(())
+    }
+    fn on_unmap_notify(&mut self, e: UnmapNotifyEvent) -> Result<(), XwmError> {
+        // a hidden dock reserves no space, it is added again once it is mapped
+        if self.docks.remove(&e.window).is_some() {
+            return self.apply_layout();
+        }
+        if !self.clients.contains_key(&e.window) {
+            return Ok(());
+        }
+        if let Some(count) = self.ignore_unmaps.get_mut(&e.window) {
+            *count -= 1;
+            if *count == 0 {
+                self.ignore_unmaps.remove(&e.window);
+            }
+            info!("Ignoring unmap of {} caused by reparenting", e.window);
+            return Ok(());
+        }
+        self.unframe(e.window)
+    }
+    fn on_configure_notify(&self, _e: ConfigureNotifyEvent) -> Result<(), XwmError> {
+        Ok(())
+    }
+    fn on_property_notify(&mut self, e: PropertyNotifyEvent) -> Result<(), XwmError> {
+        if self.docks.contains_key(&e.window) {
+            if e.atom == self.atoms._NET_WM_STRUT_PARTIAL || e.atom == self.atoms._NET_WM_STRUT {
+                let strut: Strut = self.read_strut(e.window)?;
+                self.docks.insert(e.window, strut);
+                self.apply_layout()?;
+            }
+            return Ok(());
+        }
+        if !self.clients.contains_key(&e.window) {
+            return Ok(());
+        }
+        if e.atom == self.atoms._NET_WM_NAME || e.atom == u32::from(AtomEnum::WM_NAME) {
+            self.events.emit(IpcEvent::WindowTitleChanged {
+                window: e.window,
+                title: self.window_title(e.window)?,
+            });
+            return Ok(());
+        }
+        let relevant: [u32; 3] = [
+            AtomEnum::WM_NORMAL_HINTS.into(),
+            AtomEnum::WM_HINTS.into(),
+            self.atoms.WM_PROTOCOLS,
+        ];
+        if !relevant.contains(&e.atom) {
+            return Ok(());
+        }
+
+        let hints: ClientHints = self.read_hints(e.window)?;
+        let previous: ClientHints = self.hints.insert(e.window, hints).unwrap_or_default();
+        if hints.urgent != previous.urgent {
+            info!("Client {} urgency changed to {}", e.window, hints.urgent);
+            self.update_border(e.window)?;
+        }
+        // clients which became fixed-size leave the layout
+        if hints.size.is_fixed() && !previous.size.is_fixed() && self.floating.insert(e.window) {
+            self.apply_layout()?;
+        }
+        Ok(())
+    }
+    fn on_mapping_notify(&mut self, e: MappingNotifyEvent) -> Result<(), XwmError> {
+        // keycodes may have changed, so all grabs have to be redone
+        if e.request != Mapping::POINTER {
+            self.grab_keys()?;
+        }
+        Ok(())
+    }
 
     //Request Events
-    fn on_map_request(&self, e: XMapRequestEvent) {}
-    fn on_configure_request(&self, e: XConfigureRequestEvent) {}
-}
+    fn on_map_request(&mut self, e: MapRequestEvent) -> Result<(), XwmError> {
+        if self.clients.contains_key(&e.window) {
+            self.conn.map_window(e.window)?;
+            return Ok(());
+        }
+        if self.is_dock(e.window)? {
+            self.manage_dock(e.window)?;
+            self.conn.map_window(e.window)?;
+            return self.apply_layout();
+        }
+        let focus: bool = self.frame(e.window)?;
+        self.apply_layout()?;
+        self.conn.map_window(e.window)?;
+        self.update_client_list()?;
+        if focus {
+            self.focus(e.window)?;
+        }
+        Ok(())
+    }
+    fn on_configure_request(&self, e: ConfigureRequestEvent) -> Result<(), XwmError> {
+        match self.clients.get(&e.window) {
+            Some(frame) if !self.floating.contains(&e.window) => {
+                // tiled clients get the geometry of the layout, no matter what they ask for
+                self.send_configure_notify(e.window, *frame)?;
+            }
+            Some(frame) => {
+                // the frame takes the position and size requested by the client,
+                // the client itself is kept at the origin of its frame. The requested
+                // sibling is a sibling of the client, not of the frame, so it is dropped.
+                let mut frame_changes = ConfigureWindowAux::from_configure_request(&e)
+                    .sibling(None)
+                    .border_width(self.border_width(e.window));
+                let mut client_changes = ConfigureWindowAux::new();
+                if e.value_mask
+                    .intersects(ConfigWindow::WIDTH | ConfigWindow::HEIGHT)
+                {
+                    // the size is granted within the size hints of the client
+                    let geometry = self.conn.get_geometry(*frame)?.reply()?;
+                    let width: u32 = if e.value_mask.contains(ConfigWindow::WIDTH) {
+                        e.width as u32
+                    } else {
+                        geometry.width as u32
+                    };
+                    let height: u32 = if e.value_mask.contains(ConfigWindow::HEIGHT) {
+                        e.height as u32
+                    } else {
+                        geometry.height as u32
+                    };
+                    let (width, height) = self.client_hints(e.window).size.constrain(width, height);
+                    frame_changes = frame_changes.width(width).height(height);
+                    client_changes = client_changes.width(width).height(height);
+                    info!(
+                        "Resized frame {} of client {} to {}x{}",
+                        frame, e.window, width, height
+                    );
+                }
+                self.conn.configure_window(*frame, &frame_changes)?;
+                self.conn.configure_window(e.window, &client_changes)?;
+            }
+            None => {
+                // not managed by us (yet), so the request is granted unchanged
+                self.conn
+                    .configure_window(e.window, &ConfigureWindowAux::from_configure_request(&e))?;
+            }
+        }
+        Ok(())
+    }
+
+    // EWMH requests of pagers, bars and other clients.
+    fn on_client_message(&mut self, e: ClientMessageEvent) -> Result<(), XwmError> {
+        let data: [u32; 5] = e.data.as_data32();
+        if e.type_ == self.atoms._NET_ACTIVE_WINDOW {
+            self.activate(e.window)?;
+        } else if e.type_ == self.atoms._NET_CURRENT_DESKTOP {
+            self.switch_workspace(data[0] as usize)?;
+        } else if e.type_ == self.atoms._NET_CLOSE_WINDOW && self.clients.contains_key(&e.window) {
+            self.close_window(e.window)?;
+        } else if e.type_ == self.atoms._HIPPOWM_SHUTDOWN {
+            self.running = false;
+        } else if e.type_ == self.atoms._HIPPOWM_IPC {
+            self.handle_requests()?;
+        }
+        Ok(())
+    }
+
+    // Runs the commands which arrived on the socket and replies to each of them.
+    fn handle_requests(&mut self) -> Result<(), XwmError> {
+        let requests: Vec<Request> = match &self.requests {
+            Some(requests) => requests.try_iter().collect(),
+            None => return Ok(()),
+        };
+        for request in requests {
+            info!("Received command {:?}", request.command);
+            match self.command(&request.command) {
+                Ok(reply) => request.reply(reply),
+                Err(error) => {
+                    request.reply(Reply::error(error.to_string()));
+                    if error.is_fatal() {
+                        return Err(error);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn command(&mut self, command: &Command) -> Result<Reply, XwmError> {
+        match command {
+            Command::Action { name } => {
+                if !self.action(name)? {
+                    return Ok(Reply::error(format!("Unknown action {}", name)));
+                }
+            }
+            Command::FocusWorkspace { workspace } => match self.workspace_index(workspace) {
+                Some(index) => self.switch_workspace(index)?,
+                None => return Ok(Reply::error(format!("Unknown workspace {}", workspace))),
+            },
+            Command::MoveWindow { workspace } => match self.workspace_index(workspace) {
+                Some(index) => self.move_focused_to_workspace(index)?,
+                None => return Ok(Reply::error(format!("Unknown workspace {}", workspace))),
+            },
+            Command::Spawn { program } => {
+                if let Err(error) = spawn(program.as_str()) {
+                    return Ok(Reply::error(format!(
+                        "Could not spawn {}: {}",
+                        program, error
+                    )));
+                }
+            }
+            Command::SetRatio { ratio } => {
+                let ratio: f32 = ratio.clamp(0.0, 1.0);
+                self.modify_layout(|l| l.ratio = ratio)?;
+            }
+            // answered by the socket, it never gets here
+            Command::Subscribe { .. } => {}
+            Command::GetTree | Command::GetWorkspaces | Command::GetOutputs => {
+                return Ok(self.tree()?.reply(command));
+            }
+        }
+        Ok(Reply::ok())
+    }
+
+    // The state the queries of the socket return.
+    fn tree(&self) -> Result<Tree, XwmError> {
+        let outputs: Vec<protocol::Output> = self
+            .monitors
+            .iter()
+            .enumerate()
+            .map(|(index, m)| protocol::Output {
+                index,
+                name: m.name.clone(),
+                geometry: protocol::Geometry {
+                    x: m.rect.x,
+                    y: m.rect.y,
+                    width: m.rect.w,
+                    height: m.rect.h,
+                },
+                workspace: self.workspaces[m.workspace].name.clone(),
+                focused: index == self.monitor,
+            })
+            .collect();
+        let mut workspaces: Vec<protocol::Workspace> = vec![];
+        for (index, ws) in self.workspaces.iter().enumerate() {
+            let mut clients: Vec<protocol::Client> = vec![];
+            for w in &ws.stack {
+                let frame: Window = match self.clients.get(w) {
+                    Some(frame) => *frame,
+                    None => continue,
+                };
+                let props: WindowProps = self.window_props(*w)?;
+                let geometry = self.conn.get_geometry(frame)?.reply()?;
+                clients.push(protocol::Client {
+                    window: *w,
+                    class: props.class,
+                    instance: props.instance,
+                    title: props.title,
+                    pid: self.window_pid(*w)?,
+                    floating: self.floating.contains(w),
+                    focused: self.focused == Some(*w),
+                    geometry: protocol::Geometry {
+                        x: geometry.x as i32,
+                        y: geometry.y as i32,
+                        width: geometry.width as u32,
+                        height: geometry.height as u32,
+                    },
+                });
+            }
+            workspaces.push(protocol::Workspace {
+                name: ws.name.clone(),
+                layout: ws.layout().name().to_string(),
+                output: self.monitor_of(index),
+                focused: index == self.current,
+                clients,
+            });
+        }
+        Ok(Tree {
+            outputs,
+            workspaces,
+            focused: self.focused,
+        })
+    }
+
+    // Runs one of the actions HippoWM::action knows, unknown names are ignored the same way.
+    // Returns whether the action is known.
+    fn action(&mut self, action: &str) -> Result<bool, XwmError> {
+        match action.to_lowercase().as_str() {
+            "kill" => {
+                if let Some(w) = self.focused {
+                    self.close_window(w)?;
+                }
+            }
+            "focusnext" => self.focus_offset(1)?,
+            "focusprevious" => self.focus_offset(-1)?,
+            "focusnextscreen" => self.focus_monitor(1)?,
+            "focuspreviousscreen" => self.focus_monitor(-1)?,
+            "nextlayout" => self.cycle_layout(1)?,
+            "previouslayout" => self.cycle_layout(-1)?,
+            "togglefullscreen" => self.toggle_fullscreen()?,
+            "swapup" => self.swap_offset(-1)?,
+            "swapdown" => self.swap_offset(1)?,
+            "toggletag" => self.switch_workspace(self.previous)?,
+            "floatfocused" => {
+                if let Some(w) = self.focused {
+                    self.floating.insert(w);
+                    self.apply_layout()?;
+                }
+            }
+            "incmain" => self.modify_layout(|l| l.inc_main(1))?,
+            "decmain" => self.modify_layout(|l| l.inc_main(-1))?,
+            "expandmain" => self.modify_layout(|l| l.expand_main())?,
+            "shrmain" => self.modify_layout(|l| l.shrink_main())?,
+            "restart" => self.restart()?,
+            _ => {
+                warn!("Unknown action {}", action);
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    // Moves focus through the stack, wrapping around at both ends.
+    fn focus_offset(&mut self, offset: isize) -> Result<(), XwmError> {
+        let stack: &Vec<Window> = &self.workspaces[self.current].stack;
+        let w: Window = match self.focused_index() {
+            Some(index) => stack[wrap(index, offset, stack.len())],
+            None => match stack.first() {
+                Some(w) => *w,
+                None => return Ok(()),
+            },
+        };
+        self.raise(w)?;
+        self.focus(w)
+    }
+
+    // Swaps the focused client with its neighbour in the stack, wrapping around at both ends.
+    fn swap_offset(&mut self, offset: isize) -> Result<(), XwmError> {
+        if let Some(index) = self.focused_index() {
+            let stack: &mut Vec<Window> = &mut self.workspaces[self.current].stack;
+            let other: usize = wrap(index, offset, stack.len());
+            stack.swap(index, other);
+            self.apply_layout()?;
+        }
+        Ok(())
+    }
+
+    fn focused_index(&self) -> Option<usize> {
+        let focused: Window = self.focused?;
+        self.workspaces[self.current]
+            .stack
+            .iter()
+            .position(|w| *w == focused)
+    }
+
+    fn workspace_index(&self, name: &str) -> Option<usize> {
+        self.workspaces.iter().position(|ws| ws.name == name)
+    }
+
+    // Shows the workspace with the given index on the focused monitor. A workspace which
+    // is already shown on another monitor swaps places with the current one, otherwise the
+    // current one is hidden.
+    fn switch_workspace(&mut self, index: usize) -> Result<(), XwmError> {
+        if index == self.current || index >= self.workspaces.len() {
+            return Ok(());
+        }
+        match self.monitor_of(index) {
+            // the workspaces of both monitors swap places
+            Some(other) => {
+                self.monitors[other].workspace = self.current;
+                self.show_workspace(self.current, other)?;
+                self.show_workspace(index, self.monitor)?;
+            }
+            None => {
+                // the new frames are mapped before the old ones are unmapped to avoid flicker
+                self.show_workspace(index, self.monitor)?;
+                for w in &self.workspaces[self.current].stack {
+                    if let Some(frame) = self.clients.get(w) {
+                        self.conn.unmap_window(*frame)?;
+                    }
+                }
+            }
+        }
+        self.monitors[self.monitor].workspace = index;
+        self.previous = self.current;
+        self.current = index;
+        info!("Switched to workspace {}", self.workspaces[index].name);
+        self.emit_workspace_focused();
+        self.apply_layout()?;
+        self.focus_current_workspace()?;
+        ewmh::set_current_desktop(&self.conn, self.root, &self.atoms, index)
+    }
+
+    // Tells subscribers which workspace has focus now.
+    fn emit_workspace_focused(&self) {
+        self.events.emit(IpcEvent::WorkspaceFocused {
+            workspace: self.workspaces[self.current].name.clone(),
+        });
+    }
+
+    // Gives focus to the client which had it when the current workspace was left.
+    fn focus_current_workspace(&mut self) -> Result<(), XwmError> {
+        let workspace: &Workspace = &self.workspaces[self.current];
+        match workspace.focused.or(workspace.stack.first().copied()) {
+            Some(w) => self.focus(w),
+            None => self.unfocus(),
+        }
+    }
+
+    // Moves focus to another monitor, wrapping around at both ends.
+    fn focus_monitor(&mut self, offset: isize) -> Result<(), XwmError> {
+        let m: usize = wrap(self.monitor, offset, self.monitors.len());
+        if m == self.monitor {
+            return Ok(());
+        }
+        self.monitor = m;
+        self.current = self.monitors[m].workspace;
+        info!("Focused monitor {}", self.monitors[m].name);
+        self.events.emit(IpcEvent::MonitorFocused { monitor: m });
+        self.emit_workspace_focused();
+        self.focus_current_workspace()?;
+        ewmh::set_current_desktop(&self.conn, self.root, &self.atoms, self.current)
+    }
+
+    // The monitor a workspace is shown on, None if it is hidden.
+    fn monitor_of(&self, workspace: usize) -> Option<usize> {
+        self.monitors.iter().position(|m| m.workspace == workspace)
+    }
+
+    // Maps the frames of a workspace which is now shown on the given monitor. Floating
+    // clients keep their position relative to the monitor the workspace was shown on before.
+    fn show_workspace(&mut self, index: usize, m: usize) -> Result<(), XwmError> {
+        let to: Rect = self.monitors[m].rect;
+        let workspace: &Workspace = &self.workspaces[index];
+        if let Some(from) = workspace.area.filter(|from| *from != to) {
+            for w in workspace.stack.iter().filter(|w| self.floating.contains(w)) {
+                self.move_floating(*w, from, to)?;
+            }
+        }
+        for w in &workspace.stack {
+            if let Some(frame) = self.clients.get(w) {
+                self.conn.map_window(*frame)?;
+            }
+        }
+        self.workspaces[index].area = Some(to);
+        Ok(())
+    }
+
+    // Moves the frame of a floating client from one monitor area to another.
+    fn move_floating(&self, w: Window, from: Rect, to: Rect) -> Result<(), XwmError> {
+        let frame: Window = match self.clients.get(&w) {
+            Some(frame) => *frame,
+            None => return Ok(()),
+        };
+        let geometry = self.conn.get_geometry(frame)?.reply()?;
+        let r: Rect = monitor::move_between(
+            Rect::new(
+                geometry.x as i32,
+                geometry.y as i32,
+                geometry.width as u32,
+                geometry.height as u32,
+            ),
+            from,
+            to,
+        );
+        self.conn
+            .configure_window(frame, &ConfigureWindowAux::new().x(r.x).y(r.y))?;
+        Ok(())
+    }
+
+    // Reads the monitors again, e.g. after one was connected or disconnected. Monitors keep
+    // their workspace where possible. Clients of workspaces which are no longer shown
+    // because their monitor is gone are moved to the focused monitor, instead of being
+    // left where no monitor shows them.
+    fn update_monitors(&mut self) -> Result<(), XwmError> {
+        let geometry = self.conn.get_geometry(self.root)?.reply()?;
+        self.root_rect = Rect::new(0, 0, geometry.width as u32, geometry.height as u32);
+        let outputs: Vec<(String, Rect)> =
+            monitor::query_monitors(&self.conn, self.root, self.root_rect)?;
+        let old: Vec<Monitor> = std::mem::take(&mut self.monitors);
+        self.monitors = monitor::assign_workspaces(&old, outputs, self.workspaces.len());
+        self.events.emit(IpcEvent::MonitorsChanged {
+            monitors: self.monitors.len(),
+        });
+
+        let focused: &str = &old[self.monitor].name;
+        self.monitor = self
+            .monitors
+            .iter()
+            .position(|m| m.name == focused)
+            .unwrap_or(0);
+        self.current = self.monitors[self.monitor].workspace;
+        for m in 0..self.monitors.len() {
+            self.show_workspace(self.monitors[m].workspace, m)?;
+        }
+        for m in &old {
+            if self.monitor_of(m.workspace).is_none() {
+                self.migrate_workspace(m.workspace, self.current)?;
+            }
+        }
+
+        self.apply_layout()?;
+        self.update_client_list()?;
+        let visible: bool = self
+            .focused
+            .and_then(|w| self.workspaces.iter().position(|ws| ws.contains(w)))
+            .is_some_and(|index| self.monitor_of(index).is_some());
+        if !visible {
+            self.focus_current_workspace()?;
+        }
+        ewmh::set_current_desktop(&self.conn, self.root, &self.atoms, self.current)
+    }
+
+    // Moves all clients of a workspace which is no longer shown to a shown one.
+    fn migrate_workspace(&mut self, from: usize, to: usize) -> Result<(), XwmError> {
+        let moved: Vec<Window> = std::mem::take(&mut self.workspaces[from].stack);
+        self.workspaces[from].focused = None;
+        if let (Some(from_area), Some(to_area)) =
+            (self.workspaces[from].area, self.workspaces[to].area)
+        {
+            for w in moved.iter().filter(|w| self.floating.contains(w)) {
+                self.move_floating(*w, from_area, to_area)?;
+            }
+        }
+        for w in &moved {
+            ewmh::set_wm_desktop(&self.conn, *w, &self.atoms, to)?;
+        }
+        info!(
+            "Moved {} clients of workspace {} to workspace {}",
+            moved.len(),
+            self.workspaces[from].name,
+            self.workspaces[to].name
+        );
+        self.workspaces[to].stack.extend(moved);
+        Ok(())
+    }
+
+    // Moves the focused client to the top of another workspace, where it gets focus.
+    fn move_focused_to_workspace(&mut self, index: usize) -> Result<(), XwmError> {
+        let w: Window = match self.focused {
+            Some(w) => w,
+            None => return Ok(()),
+        };
+        if index == self.current || index >= self.workspaces.len() {
+            return Ok(());
+        }
+        // transients go along with their parent
+        let transients: Vec<Window> = self
+            .transients
+            .iter()
+            .filter(|(child, parent)| {
+                **parent == w && self.workspaces[self.current].contains(**child)
+            })
+            .map(|(child, _)| *child)
+            .collect();
+        let visible: bool = self.monitor_of(index).is_some();
+        let areas: Option<(Rect, Rect)> = self.workspaces[self.current]
+            .area
+            .zip(self.workspaces[index].area);
+        for moved in std::iter::once(w).chain(transients) {
+            self.workspaces[self.current].remove(moved);
+            self.workspaces[index].stack.insert(0, moved);
+            // floating clients keep their place relative to the monitor
+            if let Some((from, to)) = areas.filter(|_| self.floating.contains(&moved)) {
+                self.move_floating(moved, from, to)?;
+            }
+            // the workspace may be shown on another monitor
+            if !visible {
+                if let Some(frame) = self.clients.get(&moved) {
+                    self.conn.unmap_window(*frame)?;
+                }
+            }
+            ewmh::set_wm_desktop(&self.conn, moved, &self.atoms, index)?;
+        }
+        self.workspaces[index].focused = Some(w);
+
+        self.focused = None;
+        self.update_border(w)?;
+        self.apply_layout()?;
+        match self.workspaces[self.current].focused {
+            Some(next) => self.focus(next)?,
+            None => self.unfocus()?,
+        }
+        self.update_client_list()
+    }
+
+    // Switches to the workspace of a client and focuses it, e.g. when a pager asks for it.
+    fn activate(&mut self, w: Window) -> Result<(), XwmError> {
+        let index: usize = match self.workspaces.iter().position(|ws| ws.contains(w)) {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+        // focusing a client on another monitor focuses that monitor
+        if self.monitor_of(index).is_none() {
+            self.switch_workspace(index)?;
+        }
+        self.raise(w)?;
+        self.focus(w)
+    }
+
+    fn cycle_layout(&mut self, offset: isize) -> Result<(), XwmError> {
+        let workspace: &mut Workspace = &mut self.workspaces[self.current];
+        workspace.layout = wrap(workspace.layout, offset, workspace.layouts.len());
+        info!("Switched to layout {}", workspace.layout().name());
+        self.events.emit(IpcEvent::LayoutChanged {
+            workspace: workspace.name.clone(),
+            layout: workspace.layout().name().to_string(),
+        });
+        self.apply_layout()
+    }
+
+    // Changes the active layout of the focused workspace, e.g. its ratio, and re-tiles.
+    fn modify_layout<F: FnOnce(&mut Layout)>(&mut self, f: F) -> Result<(), XwmError> {
+        let workspace: &mut Workspace = &mut self.workspaces[self.current];
+        f(&mut workspace.layouts[workspace.layout]);
+        self.apply_layout()
+    }
+
+    // Makes the focused client cover the whole screen, or restores it if it already does.
+    fn toggle_fullscreen(&mut self) -> Result<(), XwmError> {
+        let w: Window = match self.focused {
+            Some(w) => w,
+            None => return Ok(()),
+        };
+        let frame: Window = match self.clients.get(&w) {
+            Some(frame) => *frame,
+            None => return Ok(()),
+        };
+
+        // only one client can be fullscreen at a time
+        if let Some((fullscreen, previous)) = self.fullscreen.take() {
+            self.restore_from_fullscreen(fullscreen, previous)?;
+            if fullscreen == w {
+                return self.apply_layout();
+            }
+        }
 
-extern "C" fn on_x_error(display: *mut Display, e: *mut XErrorEvent) -> c_int {
-    const MAX_ERROR_LENGTH: usize = 1024;
-    let mut error_text: [u8; MAX_ERROR_LENGTH] = [0; MAX_ERROR_LENGTH];
-    unsafe {
-        XGetErrorText(
-            display,
-            (*e).error_code as c_int,
-            error_text.as_mut_ptr() as *mut i8,
-            MAX_ERROR_LENGTH as i32,
+        let geometry = self.conn.get_geometry(frame)?.reply()?;
+        let previous: Rect = Rect::new(
+            geometry.x as i32,
+            geometry.y as i32,
+            geometry.width as u32,
+            geometry.height as u32,
         );
+        self.fullscreen = Some((w, previous));
+        self.apply_layout()
+    }
+
+    // Gives a client which was fullscreen its border and previous geometry back.
+    fn restore_from_fullscreen(&self, w: Window, previous: Rect) -> Result<(), XwmError> {
+        let frame: Window = match self.clients.get(&w) {
+            Some(frame) => *frame,
+            None => return Ok(()),
+        };
+        self.conn.configure_window(
+            frame,
+            &ConfigureWindowAux::new()
+                .x(previous.x)
+                .y(previous.y)
+                .width(previous.w)
+                .height(previous.h)
+                .border_width(self.border_width(w)),
+        )?;
+        self.conn.configure_window(
+            w,
+            &ConfigureWindowAux::new()
+                .width(previous.w)
+                .height(previous.h),
+        )?;
+        Ok(())
+    }
+
+    // Saves the state and leaves the event loop, main execs us again with the file.
+    fn restart(&mut self) -> Result<(), XwmError> {
+        match restart::save(&self.saved_state()?) {
+            Ok(path) => {
+                self.events.emit(IpcEvent::ConfigReloaded);
+                self.restart = Some(path);
+                self.running = false;
+            }
+            Err(error) => error!("{}", error),
+        }
+        Ok(())
+    }
+
+    // Everything a restart needs to put the clients back where they are.
+    fn saved_state(&self) -> Result<SavedState, XwmError> {
+        let mut floating: HashMap<u32, Rect> = HashMap::new();
+        for w in &self.floating {
+            let frame: Window = match self.clients.get(w) {
+                Some(frame) => *frame,
+                None => continue,
+            };
+            let geometry = self.conn.get_geometry(frame)?.reply()?;
+            let r: Rect = match self.fullscreen {
+                Some((fullscreen, previous)) if fullscreen == *w => previous,
+                _ => Rect::new(
+                    geometry.x as i32,
+                    geometry.y as i32,
+                    geometry.width as u32,
+                    geometry.height as u32,
+                ),
+            };
+            floating.insert(*w, r);
+        }
+        let workspaces: Vec<SavedWorkspace> = self
+            .workspaces
+            .iter()
+            .enumerate()
+            .map(|(index, ws)| SavedWorkspace {
+                name: ws.name.clone(),
+                clients: ws.stack.clone(),
+                focused: if index == self.current {
+                    self.focused
+                } else {
+                    ws.focused
+                },
+                layout: ws.layout().name().to_string(),
+                layouts: ws
+                    .layouts
+                    .iter()
+                    .map(|l| SavedLayout {
+                        name: l.name().to_string(),
+                        max_main: l.max_main,
+                        ratio: l.ratio,
+                    })
+                    .collect(),
+            })
+            .collect();
+        Ok(SavedState {
+            current: self.workspaces[self.current].name.clone(),
+            screens: self
+                .monitors
+                .iter()
+                .map(|m| self.workspaces[m.workspace].name.clone())
+                .collect(),
+            workspaces,
+            floating,
+        })
+    }
+
+    // Shows the workspaces saved before a restart on the same monitors again. This happens
+    // before the existing clients are framed, so only the frames of shown ones are mapped.
+    fn restore_screens(&mut self, saved: &SavedState) -> Result<(), XwmError> {
+        for (m, name) in saved.screens.iter().enumerate().take(self.monitors.len()) {
+            if let Some(index) = self.workspace_index(name) {
+                if let Some(other) = self.monitor_of(index) {
+                    self.monitors[other].workspace = self.monitors[m].workspace;
+                }
+                self.monitors[m].workspace = index;
+            }
+        }
+        for m in 0..self.monitors.len() {
+            self.show_workspace(self.monitors[m].workspace, m)?;
+        }
+        self.current = self.monitors[self.monitor].workspace;
+        Ok(())
+    }
+
+    // Puts back the order of the clients, focus and layouts saved before a restart, once
+    // all existing clients were framed.
+    fn restore(&mut self, saved: SavedState) -> Result<(), XwmError> {
+        for workspace in &mut self.workspaces {
+            let s: &SavedWorkspace =
+                match saved.workspaces.iter().find(|s| s.name == workspace.name) {
+                    Some(s) => s,
+                    None => continue,
+                };
+            workspace
+                .stack
+                .sort_by_key(|w| s.clients.iter().position(|c| c == w).unwrap_or(usize::MAX));
+            if let Some(focused) = s.focused.filter(|w| workspace.contains(*w)) {
+                workspace.focused = Some(focused);
+            }
+            for layout in &mut workspace.layouts {
+                if let Some(l) = s.layouts.iter().find(|l| l.name == layout.name()) {
+                    layout.max_main = l.max_main;
+                    layout.ratio = l.ratio;
+                }
+            }
+            if let Some(layout) = workspace.layouts.iter().position(|l| l.name() == s.layout) {
+                workspace.layout = layout;
+            }
+        }
+        if let Some(index) = self.workspace_index(&saved.current) {
+            match self.monitor_of(index) {
+                Some(m) => {
+                    self.monitor = m;
+                    self.current = index;
+                }
+                None => self.switch_workspace(index)?,
+            }
+        }
+        info!("Restored the state saved before the restart");
+        self.apply_layout()?;
+        self.update_client_list()?;
+        self.focus_current_workspace()?;
+        ewmh::set_current_desktop(&self.conn, self.root, &self.atoms, self.current)
+    }
+
+    // Grabs every key combination of the config on the root window.
+    fn grab_keys(&mut self) -> Result<(), XwmError> {
+        self.keys.clear();
+        self.numlock_mask = self.numlock_mask()?;
+        self.conn.ungrab_key(Grab::ANY, self.root, ModMask::ANY)?;
+
+        let (min_keycode, keysyms_per_keycode, keysyms) = self.keyboard_mapping()?;
+
+        // alt + f4 closes the focused window unless the config uses it for something else
+        let mut actions: Vec<(String, KeyAction)> =
+            vec![("A-F4".to_string(), KeyAction::Action("kill".to_string()))];
+        actions.extend(key_actions(&self.config));
+
+        for (bind, action) in actions {
+            let key: KeyBind = match parse_bind(&bind) {
+                Some(key) => key,
+                None => {
+                    warn!("Invalid key binding {}", bind);
+                    continue;
+                }
+            };
+            let keycodes: Vec<Keycode> =
+                keycodes_for(key.keysym, min_keycode, keysyms_per_keycode, &keysyms);
+            if keycodes.is_empty() {
+                warn!("No key on this keyboard for binding {}", bind);
+                continue;
+            }
+            for keycode in keycodes {
+                for ignored in [
+                    0,
+                    u16::from(ModMask::LOCK),
+                    self.numlock_mask,
+                    self.numlock_mask | u16::from(ModMask::LOCK),
+                ] {
+                    self.conn.grab_key(
+                        true,
+                        self.root,
+                        ModMask::from(key.mask | ignored),
+                        keycode,
+                        GrabMode::ASYNC,
+                        GrabMode::ASYNC,
+                    )?;
+                }
+                self.keys.insert((key.mask, keycode), action.clone());
+            }
+        }
+        Ok(())
+    }
 
-        error!(
-            "Got X Error: {}\n Request: {}\nResource ID: {}",
-            (*e).error_code as i32,
-            (*e).request_code as i32,
-            (*e).resourceid
+    // The first keycode, the number of keysyms per keycode and all keysyms of the keyboard.
+    fn keyboard_mapping(&self) -> Result<(Keycode, u8, Vec<u32>), XwmError> {
+        let setup = self.conn.setup();
+        let (min_keycode, max_keycode) = (setup.min_keycode, setup.max_keycode);
+        let mapping = self
+            .conn
+            .get_keyboard_mapping(min_keycode, max_keycode - min_keycode + 1)?
+            .reply()?;
+        Ok((min_keycode, mapping.keysyms_per_keycode, mapping.keysyms))
+    }
+
+    // Finds the modifier NumLock is mapped to, usually Mod2.
+    fn numlock_mask(&self) -> Result<u16, XwmError> {
+        let (min_keycode, keysyms_per_keycode, keysyms) = self.keyboard_mapping()?;
+        let numlock: Vec<Keycode> = keycodes_for(
+            x11::keysym::XK_Num_Lock,
+            min_keycode,
+            keysyms_per_keycode,
+            &keysyms,
         );
+
+        let modifiers = self.conn.get_modifier_mapping()?.reply()?;
+        let per_modifier: usize = modifiers.keycodes_per_modifier() as usize;
+        let mut mask: u16 = 0;
+        if per_modifier == 0 {
+            return Ok(mask);
+        }
+        for (i, keycode) in modifiers.keycodes.iter().enumerate() {
+            if numlock.contains(keycode) {
+                mask = 1 << (i / per_modifier);
+            }
+        }
+        Ok(mask)
+    }
+
+    // Asks the client to close itself using WM_DELETE_WINDOW if it supports that protocol,
+    // otherwise the connection of the client is killed.
+    fn close_window(&self, w: Window) -> Result<(), XwmError> {
+        let protocols = self
+            .conn
+            .get_property(
+                false,
+                w,
+                self.atoms.WM_PROTOCOLS,
+                AtomEnum::ATOM,
+                0,
+                u32::MAX,
+            )?
+            .reply()?;
+        let supports_delete: bool = protocols
+            .value32()
+            .map(|mut atoms| atoms.any(|atom| atom == self.atoms.WM_DELETE_WINDOW))
+            .unwrap_or(false);
+
+        if !supports_delete {
+            info!("Window {} does not support WM_DELETE_WINDOW, killing it", w);
+            self.conn.kill_client(w)?;
+            return Ok(());
+        }
+
+        let event = ClientMessageEvent::new(
+            32,
+            w,
+            self.atoms.WM_PROTOCOLS,
+            [self.atoms.WM_DELETE_WINDOW, CURRENT_TIME, 0, 0, 0],
+        );
+        self.conn.send_event(false, w, EventMask::NO_EVENT, event)?;
+        info!("Sent WM_DELETE_WINDOW to {}", w);
+
+        // hung clients never react to the message, so they get killed after the timeout
+        if self.config.force_kill_timeout > 0 {
+            force_kill_after(
+                self.display_name.clone(),
+                w,
+                self.window_pid(w)?,
+                Duration::from_millis(self.config.force_kill_timeout),
+            );
+        }
+        Ok(())
+    }
+
+    // Reads _NET_WM_PID of the given window, not every client sets it.
+    fn window_pid(&self, w: Window) -> Result<Option<u32>, XwmError> {
+        let reply = self
+            .conn
+            .get_property(false, w, self.atoms._NET_WM_PID, AtomEnum::CARDINAL, 0, 1)?
+            .reply()?;
+        Ok(reply.value32().and_then(|mut values| values.next()))
+    }
+
+    // Grabs mod + left button (move) and mod + right button (resize) on the client, the
+    // pointer shows the move or resize cursor while the grab is active.
+    fn grab_buttons(&self, w: Window) -> Result<(), XwmError> {
+        // every click is intercepted for click to focus, the grabs with MOD_MASK below
+        // take precedence over this one
+        if self.config.focus == FocusModel::Click {
+            self.conn.grab_button(
+                false,
+                w,
+                EventMask::BUTTON_PRESS,
+                GrabMode::SYNC,
+                GrabMode::ASYNC,
+                NONE,
+                NONE,
+                ButtonIndex::M1,
+                ModMask::ANY,
+            )?;
+        }
+        for (button, cursor) in [
+            (ButtonIndex::M1, self.cursors.moving),
+            (ButtonIndex::M3, self.cursors.resizing),
+        ] {
+            // CapsLock and NumLock are ignored, the same way as for the key bindings
+            for ignored in [
+                0,
+                u16::from(ModMask::LOCK),
+                self.numlock_mask,
+                self.numlock_mask | u16::from(ModMask::LOCK),
+            ] {
+                self.conn.grab_button(
+                    false,
+                    w,
+                    EventMask::BUTTON_PRESS | EventMask::BUTTON_RELEASE | EventMask::BUTTON_MOTION,
+                    GrabMode::ASYNC,
+                    GrabMode::ASYNC,
+                    NONE,
+                    cursor,
+                    button,
+                    ModMask::from(u16::from(MOD_MASK) | ignored),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    // Frames all top-level windows which were already mapped before we took over the display.
+    // The server is grabbed meanwhile, so no window can appear or vanish while we walk the tree.
+    fn adopt_existing_windows(&mut self) -> Result<(), XwmError> {
+        self.conn.grab_server()?;
+        let result: Result<(), XwmError> = self.frame_existing_windows();
+        self.conn.ungrab_server()?;
+        result?;
+
+        self.apply_layout()?;
+        self.update_client_list()?;
+        info!("Adopted {} existing windows", self.clients.len());
+        Ok(())
+    }
+
+    fn frame_existing_windows(&mut self) -> Result<(), XwmError> {
+        let children: Vec<Window> = self.conn.query_tree(self.root)?.reply()?.children;
+
+        // all attribute requests are sent before the first reply is awaited
+        let cookies = children
+            .iter()
+            .map(|w| self.conn.get_window_attributes(*w))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut adopt: Vec<Window> = vec![];
+        for (w, cookie) in children.iter().zip(cookies) {
+            let attributes = match cookie.reply() {
+                Ok(attributes) => attributes,
+                // the window is gone already
+                Err(ReplyError::X11Error(_)) => continue,
+                Err(error) => return Err(error.into()),
+            };
+            // override redirect windows (menus, tooltips) and unmapped windows are
+            // none of our business, the latter are framed once they request a map
+            if attributes.override_redirect || attributes.map_state != MapState::VIEWABLE {
+                continue;
+            }
+            adopt.push(*w);
+        }
+
+        for w in adopt {
+            if self.is_dock(w)? {
+                self.manage_dock(w)?;
+                continue;
+            }
+            if self.frame(w)? {
+                self.focus(w)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Creates a frame window around the given client and reparents the client into it.
+    // Returns whether the client should get focus, which is not the case if it was put
+    // on a hidden workspace or a rule says so.
+    fn frame(&mut self, w: Window) -> Result<bool, XwmError> {
+        let attributes_cookie = self.conn.get_window_attributes(w)?;
+        let geometry_cookie = self.conn.get_geometry(w)?;
+        let attributes = attributes_cookie.reply()?;
+        let geometry = geometry_cookie.reply()?;
+        let hints: ClientHints = self.read_hints(w)?;
+        let parent: Option<Window> = self.transient_for(w)?;
+        let types: Vec<Atom> = self.window_types(w)?;
+        let dialog: bool = types.iter().any(|t| {
+            [
+                self.atoms._NET_WM_WINDOW_TYPE_DIALOG,
+                self.atoms._NET_WM_WINDOW_TYPE_UTILITY,
+                self.atoms._NET_WM_WINDOW_TYPE_SPLASH,
+            ]
+            .contains(t)
+        });
+        let effects: Effects = if self.rules.is_empty() {
+            Effects::default()
+        } else {
+            self.rules.effects(&self.window_props(w)?)
+        };
+        // after a restart clients go back to the workspace and geometry they had, no
+        // matter what the rules say
+        let saved_workspace: Option<usize> = self
+            .restoring
+            .as_ref()
+            .and_then(|saved| saved.workspace_of(w))
+            .and_then(|name| self.workspace_index(name));
+        let saved_geometry: Option<Rect> = self
+            .restoring
+            .as_ref()
+            .and_then(|saved| saved.floating.get(&w).copied());
+        // transients go where their parent is, a monitor rule means the workspace
+        // shown on that monitor
+        let default_workspace: usize = parent
+            .and_then(|parent| self.workspaces.iter().position(|ws| ws.contains(parent)))
+            .unwrap_or(self.current);
+        let workspace: usize = match (saved_workspace, &effects.workspace, effects.monitor) {
+            (Some(workspace), _, _) => workspace,
+            (None, Some(name), _) => self.workspace_index(name).unwrap_or_else(|| {
+                warn!("Rule for client {} names unknown workspace {}", w, name);
+                default_workspace
+            }),
+            (None, None, Some(m)) => match self.monitors.get(m) {
+                Some(m) => m.workspace,
+                None => {
+                    warn!("Rule for client {} names unknown monitor {}", w, m);
+                    default_workspace
+                }
+            },
+            (None, None, None) => default_workspace,
+        };
+        let area: Rect = self.workspace_area(workspace);
+        let floating: bool = match saved_workspace {
+            Some(_) => saved_geometry.is_some(),
+            None => effects.is_floating(hints.size.is_fixed() || parent.is_some() || dialog),
+        };
+        let border: u32 = if effects.no_border == Some(true) {
+            0
+        } else {
+            BORDER_WIDTH
+        };
+        // a rule geometry is relative to the monitor
+        let r: Rect = match (saved_geometry, effects.geometry) {
+            (Some(r), _) => r,
+            (None, Some(g)) => Rect::new(area.x + g.x, area.y + g.y, g.w.max(1), g.h.max(1)),
+            (None, None) => {
+                let r: Rect = Rect::new(
+                    geometry.x as i32,
+                    geometry.y as i32,
+                    geometry.width as u32,
+                    geometry.height as u32,
+                );
+                // dialogs are centred over their parent, or the monitor if they have none
+                if floating && (parent.is_some() || dialog) {
+                    r.centered_in(self.parent_rect(parent, area)?)
+                } else {
+                    r
+                }
+            }
+        };
+
+        // reparenting a viewable window unmaps it, which must not unframe it again
+        if attributes.map_state == MapState::VIEWABLE {
+            *self.ignore_unmaps.entry(w).or_insert(0) += 1;
+        }
+
+        let frame: Window = self.conn.generate_id()?;
+        // we want to intercept requests of the client as long as it is framed
+        let mut frame_events: EventMask =
+            EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY;
+        if self.config.focus != FocusModel::Click {
+            frame_events |= EventMask::ENTER_WINDOW;
+        }
+        let frame_attributes = CreateWindowAux::new()
+            .border_pixel(pixel(self.config.border))
+            .background_pixel(pixel(self.config.border))
+            .event_mask(frame_events);
+        self.conn.create_window(
+            x11rb::COPY_DEPTH_FROM_PARENT,
+            frame,
+            self.root,
+            r.x as i16,
+            r.y as i16,
+            r.w as u16,
+            r.h as u16,
+            border as u16,
+            WindowClass::INPUT_OUTPUT,
+            x11rb::COPY_FROM_PARENT,
+            &frame_attributes,
+        )?;
+
+        // the save set makes sure the client is restored to root, if we crash
+        self.conn.change_save_set(SetMode::INSERT, w)?;
+        self.conn.reparent_window(w, frame, 0, 0)?;
+        if saved_geometry.is_some() || effects.geometry.is_some() {
+            self.conn
+                .configure_window(w, &ConfigureWindowAux::new().width(r.w).height(r.h))?;
+        }
+        // frames on hidden workspaces are mapped once their workspace is shown
+        if self.monitor_of(workspace).is_some() {
+            self.conn.map_window(frame)?;
+        }
+        self.grab_buttons(w)?;
+        // hints may change at any time, e.g. urgency
+        self.conn.change_window_attributes(
+            w,
+            &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+        )?;
+
+        self.clients.insert(w, frame);
+        self.hints.insert(w, hints);
+        if floating {
+            self.floating.insert(w);
+        }
+        if let Some(parent) = parent {
+            self.transients.insert(w, parent);
+        }
+        if border == 0 {
+            self.borderless.insert(w);
+        }
+        if hints.iconic {
+            // there is no iconified state in Xwm, so such clients are shown right away
+            info!("Client {} asked to start iconic, mapping it normally", w);
+        }
+        if workspace == self.current {
+            // new clients take the place of the focused one, like they do in penrose
+            let position: usize = self.focused_index().unwrap_or(0);
+            self.workspaces[workspace].stack.insert(position, w);
+        } else {
+            self.workspaces[workspace].stack.insert(0, w);
+            self.workspaces[workspace].focused.get_or_insert(w);
+        }
+        ewmh::set_wm_desktop(&self.conn, w, &self.atoms, workspace)?;
+        if effects.fullscreen == Some(true) && self.fullscreen.is_none() {
+            self.fullscreen = Some((w, r));
+        }
+        info!("Framed window {} in frame {}", w, frame);
+        self.events.emit(IpcEvent::WindowOpened {
+            window: w,
+            title: self.window_title(w)?,
+        });
+        Ok(workspace == self.current && effects.focus != Some(false))
+    }
+
+    // Reparents the client back to root at its current position and destroys its frame.
+    fn unframe(&mut self, w: Window) -> Result<(), XwmError> {
+        let frame: Window = match self.clients.remove(&w) {
+            Some(frame) => frame,
+            None => return Ok(()),
+        };
+        let geometry = self.conn.get_geometry(frame)?.reply()?;
+        self.conn.unmap_window(frame)?;
+        self.conn
+            .reparent_window(w, self.root, geometry.x, geometry.y)?;
+        self.conn.change_save_set(SetMode::DELETE, w)?;
+        self.conn.destroy_window(frame)?;
+        self.forget(w)?;
+        info!("Unframed window {} from frame {}", w, frame);
+        self.apply_layout()?;
+        self.update_client_list()
+    }
+
+    // Removes every trace of a client which is no longer framed and
+    // passes focus on if it had it.
+    fn forget(&mut self, w: Window) -> Result<(), XwmError> {
+        self.events.emit(IpcEvent::WindowClosed { window: w });
+        for workspace in &mut self.workspaces {
+            workspace.remove(w);
+        }
+        self.floating.remove(&w);
+        self.hints.remove(&w);
+        self.borderless.remove(&w);
+        self.transients
+            .retain(|child, parent| *child != w && *parent != w);
+        if matches!(self.fullscreen, Some((fullscreen, _)) if fullscreen == w) {
+            self.fullscreen = None;
+        }
+        if self.focused == Some(w) {
+            self.focused = None;
+            match self.workspaces[self.current].focused {
+                Some(next) => self.focus(next)?,
+                None => self.unfocus()?,
+            }
+        }
+        Ok(())
+    }
+
+    // Positions all tiled frames on every monitor according to the active layout.
+    fn apply_layout(&self) -> Result<(), XwmError> {
+        for m in 0..self.monitors.len() {
+            self.layout_monitor(m)?;
+        }
+        if self.config.focus == FocusModel::Sloppy {
+            let pointer = self.conn.query_pointer(self.root)?.reply()?;
+            self.layout_pointer
+                .set(Some((pointer.root_x, pointer.root_y)));
+        }
+        Ok(())
+    }
+
+    // Positions the tiled frames of the workspace shown on a monitor.
+    fn layout_monitor(&self, m: usize) -> Result<(), XwmError> {
+        let workspace: &Workspace = &self.workspaces[self.monitors[m].workspace];
+        let tiled: Vec<Window> = workspace
+            .stack
+            .iter()
+            .filter(|w| !self.floating.contains(w))
+            .copied()
+            .collect();
+        let positions: Vec<Rect> =
+            workspace
+                .layout()
+                .arrange(tiled.len(), self.tiling_area(m), self.gaps);
+
+        for (w, r) in tiled.iter().zip(positions) {
+            let frame: Window = match self.clients.get(w) {
+                Some(frame) => *frame,
+                None => continue,
+            };
+            // the border is drawn outside of the frame, so it has to fit into the rect as well
+            let border: u32 = self.border_width(*w);
+            let mut width: u32 = r.w.saturating_sub(2 * border).max(1);
+            let mut height: u32 = r.h.saturating_sub(2 * border).max(1);
+            if self.config.honor_size_increments {
+                (width, height) = self.client_hints(*w).size.apply_increments(width, height);
+            }
+            self.conn.configure_window(
+                frame,
+                &ConfigureWindowAux::new()
+                    .x(r.x)
+                    .y(r.y)
+                    .width(width)
+                    .height(height),
+            )?;
+            self.conn
+                .configure_window(*w, &ConfigureWindowAux::new().width(width).height(height))?;
+        }
+
+        // the fullscreen client covers the whole monitor, including the gaps
+        if let Some((w, _)) = self.fullscreen.filter(|(w, _)| workspace.contains(*w)) {
+            if let Some(frame) = self.clients.get(&w) {
+                let screen: Rect = self.monitors[m].rect;
+                self.conn.configure_window(
+                    *frame,
+                    &ConfigureWindowAux::new()
+                        .x(screen.x)
+                        .y(screen.y)
+                        .width(screen.w)
+                        .height(screen.h)
+                        .border_width(0)
+                        .stack_mode(StackMode::ABOVE),
+                )?;
+                self.conn.configure_window(
+                    w,
+                    &ConfigureWindowAux::new().width(screen.w).height(screen.h),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    // The area of a monitor which is not reserved by docks.
+    fn tiling_area(&self, m: usize) -> Rect {
+        let struts: Vec<Strut> = self.docks.values().copied().collect();
+        self.monitors[m]
+            .rect
+            .without_struts(self.root_rect, &struts)
+    }
+
+    // The monitor area a workspace is shown on. Hidden workspaces are placed where they
+    // were shown last, or on the focused monitor if they were never shown.
+    fn workspace_area(&self, workspace: usize) -> Rect {
+        match self.monitor_of(workspace) {
+            Some(m) => self.monitors[m].rect,
+            None => self.workspaces[workspace]
+                .area
+                .unwrap_or(self.monitors[self.monitor].rect),
+        }
+    }
+
+    fn is_dock(&self, w: Window) -> Result<bool, XwmError> {
+        let types: Vec<Atom> = self.window_types(w)?;
+        Ok(types.contains(&self.atoms._NET_WM_WINDOW_TYPE_DOCK))
+    }
+
+    fn window_types(&self, w: Window) -> Result<Vec<Atom>, XwmError> {
+        let types: Vec<Atom> = self
+            .conn
+            .get_property(
+                false,
+                w,
+                self.atoms._NET_WM_WINDOW_TYPE,
+                AtomEnum::ATOM,
+                0,
+                u32::MAX,
+            )?
+            .reply()?
+            .value32()
+            .map(|types| types.collect())
+            .unwrap_or_default();
+        Ok(types)
+    }
+
+    // The client a window is transient for, if that one is managed by us.
+    fn transient_for(&self, w: Window) -> Result<Option<Window>, XwmError> {
+        let parent: Option<Window> = self
+            .conn
+            .get_property(false, w, AtomEnum::WM_TRANSIENT_FOR, AtomEnum::WINDOW, 0, 1)?
+            .reply()?
+            .value32()
+            .and_then(|mut parent| parent.next());
+        Ok(parent.filter(|parent| *parent != w && self.clients.contains_key(parent)))
+    }
+
+    // The area of the frame of a parent client, or the given monitor area if there is none.
+    fn parent_rect(&self, parent: Option<Window>, area: Rect) -> Result<Rect, XwmError> {
+        let frame: Window = match parent.and_then(|parent| self.clients.get(&parent)) {
+            Some(frame) => *frame,
+            None => return Ok(area),
+        };
+        let geometry = self.conn.get_geometry(frame)?.reply()?;
+        Ok(Rect::new(
+            geometry.x as i32,
+            geometry.y as i32,
+            geometry.width as u32,
+            geometry.height as u32,
+        ))
+    }
+
+    // Leaves a dock unframed, but keeps track of the space it reserves.
+    fn manage_dock(&mut self, w: Window) -> Result<(), XwmError> {
+        // struts may change while the dock is mapped
+        self.conn.change_window_attributes(
+            w,
+            &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+        )?;
+        let strut: Strut = self.read_strut(w)?;
+        info!("Dock {} reserves {:?}", w, strut);
+        self.docks.insert(w, strut);
+        Ok(())
+    }
+
+    // Reads _NET_WM_STRUT_PARTIAL, falling back to the older _NET_WM_STRUT.
+    fn read_strut(&self, w: Window) -> Result<Strut, XwmError> {
+        let partial_cookie = self.conn.get_property(
+            false,
+            w,
+            self.atoms._NET_WM_STRUT_PARTIAL,
+            AtomEnum::CARDINAL,
+            0,
+            12,
+        )?;
+        let strut_cookie =
+            self.conn
+                .get_property(false, w, self.atoms._NET_WM_STRUT, AtomEnum::CARDINAL, 0, 4)?;
+        let partial: Vec<u32> = partial_cookie
+            .reply()?
+            .value32()
+            .map(|values| values.collect())
+            .unwrap_or_default();
+        let strut: Vec<u32> = strut_cookie
+            .reply()?
+            .value32()
+            .map(|values| values.collect())
+            .unwrap_or_default();
+        Ok(Strut::from_partial(&partial)
+            .or_else(|| Strut::from_strut(&strut))
+            .unwrap_or_default())
+    }
+
+    // Tells a client its current geometry, used to deny configure requests of tiled clients
+    // as ICCCM 4.1.5 demands a synthetic ConfigureNotify in that case.
+    fn send_configure_notify(&self, w: Window, frame: Window) -> Result<(), XwmError> {
+        let geometry = self.conn.get_geometry(frame)?.reply()?;
+        let event = ConfigureNotifyEvent {
+            response_type: CONFIGURE_NOTIFY_EVENT,
+            sequence: 0,
+            event: w,
+            window: w,
+            above_sibling: NONE,
+            x: geometry.x + geometry.border_width as i16,
+            y: geometry.y + geometry.border_width as i16,
+            width: geometry.width,
+            height: geometry.height,
+            border_width: 0,
+            override_redirect: false,
+        };
+        self.conn
+            .send_event(false, w, EventMask::STRUCTURE_NOTIFY, event)?;
+        Ok(())
+    }
+
+    // Gives input focus to the given client the way its input model asks for
+    // and updates the frame borders accordingly.
+    fn focus(&mut self, w: Window) -> Result<(), XwmError> {
+        let old: Option<Window> = self.focused.take();
+        if let Some(old) = old {
+            self.update_border(old)?;
+        }
+        if !self.clients.contains_key(&w) {
+            return Ok(());
+        }
+        self.focused = Some(w);
+        // the monitor showing the client becomes the focused one
+        if let Some(index) = self.workspaces.iter().position(|ws| ws.contains(w)) {
+            self.workspaces[index].focused = Some(w);
+            match self.monitor_of(index) {
+                Some(m) if m != self.monitor => {
+                    self.monitor = m;
+                    self.current = index;
+                    self.events.emit(IpcEvent::MonitorFocused { monitor: m });
+                    self.emit_workspace_focused();
+                    ewmh::set_current_desktop(&self.conn, self.root, &self.atoms, index)?;
+                }
+                _ => {}
+            }
+        }
+        self.update_border(w)?;
+        ewmh::set_active_window(&self.conn, self.root, &self.atoms, Some(w))?;
+        if old != Some(w) {
+            self.events.emit(IpcEvent::WindowFocused {
+                window: Some(w),
+                title: self.window_title(w)?,
+            });
+        }
+
+        let input: InputModel = self.client_hints(w).input;
+        if input.set_focus() {
+            self.conn
+                .set_input_focus(InputFocus::POINTER_ROOT, w, CURRENT_TIME)?;
+        }
+        if input.take_focus() {
+            let event = ClientMessageEvent::new(
+                32,
+                w,
+                self.atoms.WM_PROTOCOLS,
+                [self.atoms.WM_TAKE_FOCUS, CURRENT_TIME, 0, 0, 0],
+            );
+            self.conn.send_event(false, w, EventMask::NO_EVENT, event)?;
+        }
+        Ok(())
+    }
+
+    // Leaves no client focused, keyboard input goes to the root window.
+    fn unfocus(&mut self) -> Result<(), XwmError> {
+        if let Some(old) = self.focused.take() {
+            self.update_border(old)?;
+            self.events.emit(IpcEvent::WindowFocused {
+                window: None,
+                title: String::new(),
+            });
+        }
+        self.conn
+            .set_input_focus(InputFocus::POINTER_ROOT, self.root, CURRENT_TIME)?;
+        ewmh::set_active_window(&self.conn, self.root, &self.atoms, None)
+    }
+
+    // Raises the frame of a client above all other windows, its transients stay above it.
+    fn raise(&self, w: Window) -> Result<(), XwmError> {
+        let transients = self
+            .transients
+            .iter()
+            .filter(|(_, parent)| **parent == w)
+            .map(|(child, _)| child);
+        for raised in std::iter::once(&w).chain(transients) {
+            if let Some(frame) = self.clients.get(raised) {
+                self.conn.configure_window(
+                    *frame,
+                    &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE),
+                )?;
+            }
+        }
+        self.update_client_list()
+    }
+
+    // Publishes _NET_CLIENT_LIST and _NET_CLIENT_LIST_STACKING. The stacking order is
+    // the order of the frames among the children of root, which is bottom to top.
+    fn update_client_list(&self) -> Result<(), XwmError> {
+        let clients: Vec<Window> = self
+            .workspaces
+            .iter()
+            .flat_map(|ws| ws.stack.iter().copied())
+            .collect();
+        let by_frame: HashMap<Window, Window> =
+            self.clients.iter().map(|(w, frame)| (*frame, *w)).collect();
+        let stacking: Vec<Window> = self
+            .conn
+            .query_tree(self.root)?
+            .reply()?
+            .children
+            .iter()
+            .filter_map(|frame| by_frame.get(frame).copied())
+            .collect();
+        ewmh::set_client_list(&self.conn, self.root, &self.atoms, &clients, &stacking)
+    }
+
+    // Hands the bar what it shows, which may have changed with every event. The bar is
+    // drawn on the first monitor.
+    fn update_bar(&self) -> Result<(), XwmError> {
+        let bar: &Bar = match &self.bar {
+            Some(bar) => bar,
+            None => return Ok(()),
+        };
+        let title: String = match self.focused {
+            Some(w) => self.window_title(w)?,
+            None => String::new(),
+        };
+        bar.update(Status {
+            workspaces: self
+                .workspaces
+                .iter()
+                .enumerate()
+                .map(|(index, ws)| WorkspaceStatus {
+                    name: ws.name.clone(),
+                    focused: index == self.current,
+                    occupied: !ws.stack.is_empty(),
+                    urgent: ws.stack.iter().any(|w| self.client_hints(*w).urgent),
+                })
+                .collect(),
+            layout: self.workspaces[self.current].layout().name().to_string(),
+            title,
+            area: self.monitors.first().map_or(self.root_rect, |m| m.rect),
+        });
+        Ok(())
+    }
+
+    // Colours the frame border of a client by focus and urgency.
+    fn update_border(&self, w: Window) -> Result<(), XwmError> {
+        let frame: Window = match self.clients.get(&w) {
+            Some(frame) => *frame,
+            None => return Ok(()),
+        };
+        let color: u32 = if self.focused == Some(w) {
+            self.config.focused_border
+        } else if self.client_hints(w).urgent {
+            self.config.urgent_border
+        } else {
+            self.config.border
+        };
+        self.conn.change_window_attributes(
+            frame,
+            &ChangeWindowAttributesAux::new().border_pixel(pixel(color)),
+        )?;
+        Ok(())
+    }
+
+    fn read_hints(&self, w: Window) -> Result<ClientHints, XwmError> {
+        let hints: ClientHints = get_client_hints(
+            &self.conn,
+            w,
+            self.atoms.WM_PROTOCOLS,
+            self.atoms.WM_TAKE_FOCUS,
+        )?;
+        Ok(hints)
+    }
+
+    // The hints of a client, clients without hints are treated as having none set.
+    fn client_hints(&self, w: Window) -> ClientHints {
+        self.hints.get(&w).copied().unwrap_or_default()
+    }
+
+    fn border_width(&self, w: Window) -> u32 {
+        if self.borderless.contains(&w) {
+            return 0;
+        }
+        BORDER_WIDTH
+    }
+
+    // The title of a client, _NET_WM_NAME if it set one and WM_NAME otherwise.
+    fn window_title(&self, w: Window) -> Result<String, XwmError> {
+        let net_name_cookie = self.conn.get_property(
+            false,
+            w,
+            self.atoms._NET_WM_NAME,
+            self.atoms.UTF8_STRING,
+            0,
+            u32::MAX,
+        )?;
+        let name_cookie =
+            self.conn
+                .get_property(false, w, AtomEnum::WM_NAME, AtomEnum::ANY, 0, u32::MAX)?;
+        let net_name: Vec<u8> = net_name_cookie.reply()?.value;
+        if !net_name.is_empty() {
+            return Ok(String::from_utf8_lossy(&net_name).into_owned());
+        }
+        Ok(String::from_utf8_lossy(&name_cookie.reply()?.value).into_owned())
+    }
+
+    // Reads the properties window rules are matched against, unset ones are left empty.
+    fn window_props(&self, w: Window) -> Result<WindowProps, XwmError> {
+        let class_cookie = WmClass::get(&self.conn, w)?;
+        let net_name_cookie = self.conn.get_property(
+            false,
+            w,
+            self.atoms._NET_WM_NAME,
+            self.atoms.UTF8_STRING,
+            0,
+            u32::MAX,
+        )?;
+        let name_cookie =
+            self.conn
+                .get_property(false, w, AtomEnum::WM_NAME, AtomEnum::ANY, 0, u32::MAX)?;
+        let role_cookie = self.conn.get_property(
+            false,
+            w,
+            self.atoms.WM_WINDOW_ROLE,
+            AtomEnum::STRING,
+            0,
+            u32::MAX,
+        )?;
+        let types_cookie = self.conn.get_property(
+            false,
+            w,
+            self.atoms._NET_WM_WINDOW_TYPE,
+            AtomEnum::ATOM,
+            0,
+            u32::MAX,
+        )?;
+
+        let mut props: WindowProps = WindowProps::default();
+        // a malformed WM_CLASS is treated like a missing one
+        if let Ok(Some(class)) = class_cookie.reply() {
+            props.instance = String::from_utf8_lossy(class.instance()).into_owned();
+            props.class = String::from_utf8_lossy(class.class()).into_owned();
+        }
+        let net_name: Vec<u8> = net_name_cookie.reply()?.value;
+        let name: Vec<u8> = name_cookie.reply()?.value;
+        props.title = if net_name.is_empty() {
+            String::from_utf8_lossy(&name).into_owned()
+        } else {
+            String::from_utf8_lossy(&net_name).into_owned()
+        };
+        props.role = String::from_utf8_lossy(&role_cookie.reply()?.value).into_owned();
+
+        let types: Vec<u32> = types_cookie
+            .reply()?
+            .value32()
+            .map(|types| types.collect())
+            .unwrap_or_default();
+        let name_cookies = types
+            .iter()
+            .map(|t| self.conn.get_atom_name(*t))
+            .collect::<Result<Vec<_>, _>>()?;
+        for cookie in name_cookies {
+            props
+                .types
+                .push(String::from_utf8_lossy(&cookie.reply()?.name).into_owned());
+        }
+        Ok(props)
+    }
+}
+
+impl Drop for Xwm {
+    // Restores the clients when run returns early with an error, or panics.
+    fn drop(&mut self) {
+        self.close();
     }
-    return 0;
 }
 
-extern "C" fn on_detected_wm(display: *mut Display, e: *mut XErrorEvent) -> c_int {
-    //Since the only error code we expect in this error handler, we
-    //only need to check for that.
-    unsafe {
-        assert_eq!((*e).error_code as i32, BadAccess.into());
-        WM_DETECTED = true;
+// Moves index by offset in a list of the given length, wrapping around at both ends.
+fn wrap(index: usize, offset: isize, len: usize) -> usize {
+    if len == 0 {
+        return 0;
     }
+    (index as isize + offset).rem_euclid(len as isize) as usize
+}
 
-    return 0;
+// Colours in the config are stored as 0xRRGGBBAA, X expects a 0xRRGGBB pixel value.
+fn pixel(color: u32) -> u32 {
+    color >> 8
 }